@@ -0,0 +1,316 @@
+use crate::{
+    config::{AlertConfig, AlertStyle, ModuleSelector},
+    matrix::Matrix,
+    power::{BatteryReading, BatterySource},
+    widget::{OFF, ON_DIM, ON_FULL},
+};
+
+/// What is currently being played over the widgets
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Playing {
+    LowBattery,
+    Charging,
+}
+
+///
+/// Watches the battery, and plays an alert over the widgets when the charge drops
+/// below the configured threshold while discharging, or a short animation when the
+/// charger is plugged in
+///
+pub struct Alert {
+    source: Box<dyn BatterySource>,
+    config: AlertConfig,
+    last: Option<BatteryReading>,
+    /// What is playing, and how many frames of it have been shown
+    playing: Option<(Playing, usize)>,
+}
+
+impl Alert {
+    pub fn new(source: Box<dyn BatterySource>, config: AlertConfig) -> Alert {
+        Alert {
+            source,
+            config,
+            last: None,
+            playing: None,
+        }
+    }
+
+    ///
+    /// The module the alert is drawn on, every module if None
+    ///
+    pub fn module(&self) -> Option<&ModuleSelector> {
+        self.config.module.as_ref()
+    }
+
+    ///
+    /// Whether anything is drawn over the widgets this frame
+    ///
+    pub fn is_active(&self) -> bool {
+        self.playing.is_some()
+    }
+
+    ///
+    /// Read the battery and advance to the next frame. Starts the alert when the
+    /// battery just dropped below the threshold (or already was on startup), and the
+    /// charging animation when the charger was just plugged in.
+    ///
+    pub fn update(&mut self) {
+        self.playing = match self.playing {
+            Some((playing, frame)) if frame + 1 < self.frames(playing) => {
+                Some((playing, frame + 1))
+            }
+            _ => None,
+        };
+
+        let reading = match self.source.read() {
+            Ok(readings) => BatteryReading::combine(&readings),
+            Err(e) => {
+                println!("Alert: Unable to read batteries: {}", e);
+                return;
+            }
+        };
+        let Some(reading) = reading else {
+            return;
+        };
+
+        let low = |r: &BatteryReading| {
+            r.state == battery::State::Discharging && r.percent() < self.config.threshold
+        };
+        let charging = |r: &BatteryReading| r.state == battery::State::Charging;
+
+        let started = match self.last {
+            _ if !low(&reading) && !charging(&reading) => None,
+            Some(last) if low(&reading) && !low(&last) => Some(Playing::LowBattery),
+            None if low(&reading) => Some(Playing::LowBattery),
+            Some(last) if self.config.charging && charging(&reading) && !charging(&last) => {
+                Some(Playing::Charging)
+            }
+            _ => None,
+        };
+        if let Some(playing) = started.filter(|&p| self.frames(p) > 0) {
+            self.playing = Some((playing, 0));
+        }
+
+        self.last = Some(reading);
+    }
+
+    ///
+    /// Number of frames an alert plays for. The low battery alert alternates
+    /// between on and off for every cycle, the charging animation fills up once.
+    /// Nothing is played with 0 cycles.
+    ///
+    fn frames(&self, playing: Playing) -> usize {
+        match (playing, self.config.cycles) {
+            (_, 0) => 0,
+            (Playing::LowBattery, cycles) => cycles * 2,
+            (Playing::Charging, cycles) => cycles + 1,
+        }
+    }
+
+    ///
+    /// Draw the current frame of the alert over a module's frame
+    ///
+    pub fn overlay(&self, frame: Matrix) -> Matrix {
+        let Some((playing, step)) = self.playing else {
+            return frame;
+        };
+
+        let mut out = frame;
+        let (x0, y0) = (self.config.x.min(8), self.config.y.min(33));
+        let w = self.config.width.unwrap_or(9).clamp(1, 9 - x0);
+        let h = self.config.height.unwrap_or(34).clamp(1, 34 - y0);
+
+        for y in 0..h {
+            for x in 0..w {
+                let led = match playing {
+                    Playing::LowBattery => self.low_battery_led(step, x, y, w, h),
+                    Playing::Charging => Self::charging_led(step, self.frames(playing), y, h),
+                };
+                if let Some(led) = led {
+                    out[y0 + y][x0 + x] = led;
+                }
+            }
+        }
+
+        out
+    }
+
+    ///
+    /// One LED of the low battery alert, None leaves the widgets underneath visible.
+    /// Every other frame is the alert, the frames in between show the widgets.
+    ///
+    fn low_battery_led(&self, step: usize, x: usize, y: usize, w: usize, h: usize) -> Option<u8> {
+        if step % 2 == 1 {
+            return None;
+        }
+
+        match self.config.style {
+            AlertStyle::Flash => Some(ON_FULL),
+            // An exclamation mark inside a border
+            AlertStyle::Warning => {
+                let border = x == 0 || y == 0 || x == w - 1 || y == h - 1;
+                let column = ((w - 1) / 2..=w / 2).contains(&x);
+                let mark = column && ((y >= 3 && y + 7 < h) || (y + 5 >= h && y + 3 < h));
+                Some(if border || mark { ON_FULL } else { OFF })
+            }
+        }
+    }
+
+    ///
+    /// One LED of the charging animation, which fills the region from the bottom up
+    ///
+    fn charging_led(step: usize, frames: usize, y: usize, h: usize) -> Option<u8> {
+        let lit = h * step / (frames - 1);
+        Some(if h - y <= lit { ON_DIM } else { OFF })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockBatteries;
+    use battery::State;
+
+    const BLANK: Matrix = [[0; 9]; 34];
+
+    fn alert(percents: &[(f32, State)], config: AlertConfig) -> Alert {
+        let reads = percents
+            .iter()
+            .map(|&(pct, state)| Some(vec![MockBatteries::reading(pct, state)]))
+            .collect();
+        Alert::new(Box::new(MockBatteries::new(reads)), config)
+    }
+
+    fn config() -> AlertConfig {
+        serde_json::from_str(r#"{ "threshold": 10, "cycles": 2 }"#).unwrap()
+    }
+
+    /// Which frames had the alert drawn over the widgets
+    fn played(alert: &mut Alert, frames: usize) -> Vec<bool> {
+        (0..frames)
+            .map(|_| {
+                alert.update();
+                alert.overlay(BLANK) != BLANK
+            })
+            .collect()
+    }
+
+    #[test]
+    fn low_battery_flashes_once() {
+        let mut alert = alert(
+            &[
+                (12.0, State::Discharging),
+                (9.0, State::Discharging),
+                (9.0, State::Discharging),
+                (8.0, State::Discharging),
+                (8.0, State::Discharging),
+                (7.0, State::Discharging),
+            ],
+            config(),
+        );
+
+        let flashes = played(&mut alert, 6);
+        assert_eq!(flashes, [false, true, false, true, false, false]);
+        assert!(!alert.is_active());
+    }
+
+    #[test]
+    fn low_battery_while_charging_is_quiet() {
+        let mut cfg = config();
+        cfg.charging = false;
+        let mut alert = alert(
+            &[
+                (5.0, State::Charging),
+                (5.0, State::Charging),
+                (5.0, State::Full),
+            ],
+            cfg,
+        );
+
+        assert_eq!(played(&mut alert, 3), [false, false, false]);
+    }
+
+    #[test]
+    fn zero_cycles_never_play() {
+        let mut cfg = config();
+        cfg.cycles = 0;
+        let mut alert = alert(
+            &[
+                (5.0, State::Discharging),
+                (5.0, State::Charging),
+                (4.0, State::Discharging),
+            ],
+            cfg,
+        );
+
+        assert_eq!(played(&mut alert, 3), [false, false, false]);
+        assert!(!alert.is_active());
+    }
+
+    #[test]
+    fn low_battery_on_startup() {
+        let mut alert = alert(&[(5.0, State::Discharging)], config());
+        alert.update();
+
+        let frame = alert.overlay(BLANK);
+        assert!(frame.iter().flatten().all(|&led| led == ON_FULL));
+    }
+
+    #[test]
+    fn charging_animation_fills_region() {
+        let mut cfg = config();
+        cfg.y = 30;
+        cfg.height = Some(4);
+        let mut alert = alert(
+            &[
+                (50.0, State::Discharging),
+                (50.0, State::Charging),
+                (50.0, State::Charging),
+                (50.0, State::Charging),
+            ],
+            cfg,
+        );
+
+        alert.update();
+        assert!(!alert.is_active());
+
+        // Nothing, half, then all of the region is filled
+        let lit_rows = |frame: Matrix| {
+            frame
+                .iter()
+                .filter(|row| row.iter().all(|&led| led == ON_DIM))
+                .count()
+        };
+        for rows in [0, 2, 4] {
+            alert.update();
+            assert!(alert.is_active());
+            let frame = alert.overlay(BLANK);
+            assert_eq!(lit_rows(frame), rows);
+            assert!(frame[..30].iter().flatten().all(|&led| led == OFF));
+        }
+    }
+
+    #[test]
+    fn warning_pattern() {
+        let mut cfg = config();
+        cfg.style = AlertStyle::Warning;
+        let mut alert = alert(&[(5.0, State::Discharging)], cfg);
+        alert.update();
+
+        let frame = alert.overlay(BLANK);
+        assert_eq!(frame[0], [ON_FULL; 9]);
+        assert_eq!(frame[33], [ON_FULL; 9]);
+        assert_eq!(
+            frame[10],
+            [ON_FULL, OFF, OFF, OFF, ON_FULL, OFF, OFF, OFF, ON_FULL]
+        );
+        assert_eq!(
+            frame[28],
+            [ON_FULL, OFF, OFF, OFF, OFF, OFF, OFF, OFF, ON_FULL]
+        );
+        assert_eq!(
+            frame[30],
+            [ON_FULL, OFF, OFF, OFF, ON_FULL, OFF, OFF, OFF, ON_FULL]
+        );
+    }
+}
@@ -0,0 +1,833 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{
+    de::{DeserializeOwned, Error as _},
+    Deserialize, Deserializer,
+};
+use serde_json::{Map, Value};
+
+use crate::{
+    font::Font,
+    layout::{Align, Direction, LayoutError, Node, Placement, Stack},
+    power::SharedBatteries,
+    widget::{
+        AllCPUsWidget, BatteryMode, BatteryWidget, ClockMode, ClockSeconds, ClockWidget,
+        CpuAggregate, CpuHistoryWidget, DiskWidget, FontChoice, ImageWidget, MemoryStyle,
+        MemoryWidget, NetworkWidget, Shape, TemperatureWidget, TextWidget, UpdatableWidget,
+    },
+};
+
+///
+/// Top level layout of the JSON configuration file. Every field is optional,
+/// anything left out falls back to the built-in defaults.
+///
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Refresh rate, in Hz
+    pub rate: Option<f64>,
+    /// Global brightness applied to every module on startup (0=OFF, 255=FULL)
+    pub brightness: Option<u8>,
+    /// USB serial numbers of the left and right modules
+    pub modules: Option<ModuleSides>,
+    /// Widgets to display, and where to display them
+    pub widgets: Option<Vec<WidgetConfig>>,
+    /// Low battery alert and charging animation, drawn over the widgets
+    pub alert: Option<AlertConfig>,
+    /// Widgets placed by the layout engine, one stack per area of a module
+    pub layouts: Option<Vec<LayoutConfig>>,
+}
+
+///
+/// Pins the "left" and "right" modules to USB serial numbers, so the assignment
+/// doesn't depend on the order the modules are enumerated in
+///
+#[derive(Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ModuleSides {
+    pub left: Option<String>,
+    pub right: Option<String>,
+}
+
+///
+/// How the low battery alert is drawn
+///
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertStyle {
+    /// Light up the whole region
+    #[default]
+    Flash,
+    /// An exclamation mark inside a border
+    Warning,
+}
+
+///
+/// When and where the low battery alert is shown. The region defaults to the whole
+/// module, and the alert is shown on every module unless one is selected.
+///
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AlertConfig {
+    /// Charge (in percent) below which the alert is shown while discharging
+    #[serde(default = "default_alert_threshold")]
+    pub threshold: f32,
+    #[serde(default)]
+    pub style: AlertStyle,
+    /// How many times the alert flashes, or how many steps the charging animation has.
+    /// Neither is played with 0.
+    #[serde(default = "default_alert_cycles")]
+    pub cycles: usize,
+    /// Play an animation when the charger is plugged in
+    #[serde(default = "default_true")]
+    pub charging: bool,
+    pub module: Option<ModuleSelector>,
+    #[serde(default)]
+    pub x: usize,
+    #[serde(default)]
+    pub y: usize,
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+}
+
+fn default_alert_threshold() -> f32 {
+    10.0
+}
+
+fn default_alert_cycles() -> usize {
+    3
+}
+
+fn default_true() -> bool {
+    true
+}
+
+///
+/// A single widget along with its placement on a module
+///
+#[derive(Clone)]
+pub struct WidgetConfig {
+    pub kind: WidgetKind,
+    pub x: usize,
+    pub y: usize,
+    pub module: ModuleSelector,
+}
+
+/// The keys of a WidgetConfig that aren't widget options
+#[derive(Deserialize)]
+struct WidgetPosition {
+    #[serde(default)]
+    x: usize,
+    #[serde(default)]
+    y: usize,
+    #[serde(default)]
+    module: ModuleSelector,
+}
+
+impl<'de> Deserialize<'de> for WidgetConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (pos, kind): (WidgetPosition, WidgetKind) =
+            split_keys(deserializer, &["x", "y", "module"])?;
+        Ok(WidgetConfig {
+            kind,
+            x: pos.x,
+            y: pos.y,
+            module: pos.module,
+        })
+    }
+}
+
+///
+/// Deserialize an object in two parts, the given keys into one type and the rest into
+/// the other. Unlike `#[serde(flatten)]`, this still lets both parts reject unknown keys.
+///
+fn split_keys<'de, D, A, B>(deserializer: D, keys: &[&str]) -> Result<(A, B), D::Error>
+where
+    D: Deserializer<'de>,
+    A: DeserializeOwned,
+    B: DeserializeOwned,
+{
+    let mut rest = Map::deserialize(deserializer)?;
+    let picked: Map<String, Value> = keys
+        .iter()
+        .filter_map(|&key| rest.remove_entry(key))
+        .collect();
+    let a = A::deserialize(Value::Object(picked)).map_err(D::Error::custom)?;
+    let b = B::deserialize(Value::Object(rest)).map_err(D::Error::custom)?;
+    Ok((a, b))
+}
+
+///
+/// Every widget that can be created from the configuration file, along with
+/// its widget specific options
+///
+#[derive(Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum WidgetKind {
+    Battery {
+        index: Option<usize>,
+        #[serde(default)]
+        mode: BatteryMode,
+    },
+    Cpu {
+        #[serde(default)]
+        merge_threads: bool,
+        rows: Option<usize>,
+        #[serde(default)]
+        aggregate: CpuAggregate,
+    },
+    Clock {
+        #[serde(default)]
+        twelve_hour: bool,
+        #[serde(default)]
+        seconds: ClockSeconds,
+        #[serde(default)]
+        mode: ClockMode,
+        timezone: Option<String>,
+    },
+    Memory {
+        #[serde(default)]
+        style: MemoryStyle,
+        #[serde(default)]
+        swap: bool,
+        width: Option<usize>,
+        height: Option<usize>,
+    },
+    Disk {
+        mounts: Option<Vec<String>>,
+        #[serde(default = "default_warn_pct")]
+        warn_pct: f32,
+        #[serde(default = "default_bar_height")]
+        bar_height: usize,
+    },
+    Network {
+        interface: Option<String>,
+        width: Option<usize>,
+        height: Option<usize>,
+    },
+    CpuHistory {
+        #[serde(default)]
+        percent: bool,
+        width: Option<usize>,
+        height: Option<usize>,
+    },
+    Temperature {
+        sensor: Option<String>,
+        #[serde(default = "default_warn_temp")]
+        warn: f32,
+        #[serde(default = "default_crit_temp")]
+        crit: f32,
+        #[serde(default)]
+        fan: bool,
+        height: Option<usize>,
+    },
+    Text {
+        text: String,
+        #[serde(default)]
+        font: FontChoice,
+        /// BDF or PSF font to use instead of the built-in ones
+        font_file: Option<PathBuf>,
+        font_height: Option<usize>,
+        #[serde(default)]
+        rotated: bool,
+        length: Option<usize>,
+        #[serde(default = "default_scroll")]
+        scroll: usize,
+    },
+    Image {
+        path: PathBuf,
+        width: Option<usize>,
+        height: Option<usize>,
+        #[serde(default)]
+        dither: bool,
+    },
+}
+
+fn default_scroll() -> usize {
+    1
+}
+
+fn default_warn_temp() -> f32 {
+    70.0
+}
+
+fn default_crit_temp() -> f32 {
+    90.0
+}
+
+fn default_warn_pct() -> f32 {
+    90.0
+}
+
+fn default_bar_height() -> usize {
+    2
+}
+
+///
+/// A stack of widgets laid out automatically in an area of a module, the whole
+/// module unless the area is given
+///
+#[derive(Clone)]
+pub struct LayoutConfig {
+    pub module: ModuleSelector,
+    pub x: usize,
+    pub y: usize,
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub stack: StackConfig,
+}
+
+/// The keys of a LayoutConfig that aren't stack options
+#[derive(Deserialize)]
+struct LayoutArea {
+    #[serde(default)]
+    module: ModuleSelector,
+    #[serde(default)]
+    x: usize,
+    #[serde(default)]
+    y: usize,
+    width: Option<usize>,
+    height: Option<usize>,
+}
+
+impl<'de> Deserialize<'de> for LayoutConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let keys = ["module", "x", "y", "width", "height"];
+        let (area, stack): (LayoutArea, StackConfig) = split_keys(deserializer, &keys)?;
+        Ok(LayoutConfig {
+            module: area.module,
+            x: area.x,
+            y: area.y,
+            width: area.width,
+            height: area.height,
+            stack,
+        })
+    }
+}
+
+///
+/// Items placed one after another, see layout::Stack
+///
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct StackConfig {
+    #[serde(default)]
+    pub direction: Direction,
+    #[serde(default)]
+    pub align: Align,
+    #[serde(default)]
+    pub cross_align: Align,
+    #[serde(default)]
+    pub padding: usize,
+    #[serde(default)]
+    pub spacing: usize,
+    pub items: Vec<LayoutItem>,
+}
+
+///
+/// Anything that can go in a stack. Entries with a widget "type" are widgets.
+///
+#[derive(Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LayoutItem {
+    Stack(StackConfig),
+    Spacer {
+        size: usize,
+    },
+    #[serde(untagged)]
+    Widget(WidgetKind),
+}
+
+///
+/// Which LED matrix module a widget is drawn on. "left" and "right" use the serial
+/// numbers in ModuleSides, or the connected modules sorted by serial number if they
+/// aren't set. "span" places the widget on an 18x34 canvas covering both modules,
+/// where x=0-8 is the left module and x=9-17 the right one.
+/// Anything else is treated as a serial port name or USB serial number.
+///
+#[derive(Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(from = "String")]
+pub enum ModuleSelector {
+    #[default]
+    Left,
+    Right,
+    Span,
+    Device(String),
+}
+
+impl From<String> for ModuleSelector {
+    fn from(s: String) -> Self {
+        match s.to_lowercase().as_str() {
+            "left" => ModuleSelector::Left,
+            "right" => ModuleSelector::Right,
+            "span" => ModuleSelector::Span,
+            _ => ModuleSelector::Device(s),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    /// A refresh rate that isn't a positive number, or is faster than MAX_RATE
+    Rate(f64),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "Unable to read config file: {}", e),
+            ConfigError::Parse(e) => write!(f, "Invalid config file: {}", e),
+            ConfigError::Rate(rate) => write!(
+                f,
+                "Invalid refresh rate {}: it must be above 0 and at most {} Hz",
+                rate, MAX_RATE
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    ///
+    /// Read and parse a JSON configuration file
+    ///
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let contents = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        serde_json::from_str(&contents).map_err(ConfigError::Parse)
+    }
+}
+
+/// Fastest refresh rate the modules keep up with when drawn with brightness control
+pub const MAX_RATE: f64 = 5.5;
+
+///
+/// Make sure a refresh rate can be turned into a frame period the modules keep up with
+///
+pub fn check_rate(rate: f64) -> Result<f64, ConfigError> {
+    if rate > 0.0 && rate <= MAX_RATE {
+        Ok(rate)
+    } else {
+        Err(ConfigError::Rate(rate))
+    }
+}
+
+impl WidgetKind {
+    ///
+    /// Create the widget described by this configuration entry
+    ///
+    pub fn build(&self) -> Box<dyn UpdatableWidget> {
+        match self {
+            WidgetKind::Battery { index, mode } => Box::new(BatteryWidget::new(
+                Box::new(SharedBatteries::system()),
+                *index,
+                *mode,
+            )),
+            WidgetKind::Cpu {
+                merge_threads,
+                rows,
+                aggregate,
+            } => Box::new(AllCPUsWidget::new(*merge_threads, *rows, *aggregate)),
+            WidgetKind::Clock {
+                twelve_hour,
+                seconds,
+                mode,
+                timezone,
+            } => Box::new(ClockWidget::new(
+                *twelve_hour,
+                *seconds,
+                *mode,
+                timezone.as_deref(),
+            )),
+            WidgetKind::Memory {
+                style,
+                swap,
+                width,
+                height,
+            } => Box::new(MemoryWidget::new(*style, *swap, *width, *height)),
+            WidgetKind::Disk {
+                mounts,
+                warn_pct,
+                bar_height,
+            } => Box::new(DiskWidget::new(
+                mounts
+                    .clone()
+                    .unwrap_or_else(|| vec![DiskWidget::default_mount().to_string()]),
+                *warn_pct,
+                *bar_height,
+            )),
+            WidgetKind::Network {
+                interface,
+                width,
+                height,
+            } => Box::new(NetworkWidget::new(interface.clone(), *width, *height)),
+            WidgetKind::CpuHistory {
+                percent,
+                width,
+                height,
+            } => Box::new(CpuHistoryWidget::new(*percent, *width, *height)),
+            WidgetKind::Temperature {
+                sensor,
+                warn,
+                crit,
+                fan,
+                height,
+            } => Box::new(TemperatureWidget::new(
+                sensor.as_deref(),
+                *warn,
+                *crit,
+                fan.then(|| PathBuf::from(TemperatureWidget::HWMON_ROOT)),
+                *height,
+            )),
+            WidgetKind::Text {
+                text,
+                font,
+                font_file,
+                font_height,
+                rotated,
+                length,
+                scroll,
+            } => {
+                // Fall back to the built-in font rather than not showing the text at all
+                let loaded = font_file.as_ref().and_then(|path| {
+                    Font::load(path, *font_height)
+                        .inspect_err(|e| println!("{}: {}", path.display(), e))
+                        .ok()
+                });
+                Box::new(TextWidget::new(
+                    text,
+                    loaded.as_ref().unwrap_or(font.font()),
+                    *rotated,
+                    *length,
+                    *scroll,
+                ))
+            }
+            WidgetKind::Image {
+                path,
+                width,
+                height,
+                dither,
+            } => Box::new(ImageWidget::new(path, *width, *height, *dither)),
+        }
+    }
+}
+
+impl StackConfig {
+    ///
+    /// Create the widgets of the stack, and of any stacks inside of it
+    ///
+    pub fn build(&self) -> Stack {
+        Stack {
+            direction: self.direction,
+            align: self.align,
+            cross_align: self.cross_align,
+            padding: self.padding,
+            spacing: self.spacing,
+            items: self
+                .items
+                .iter()
+                .map(|item| match item {
+                    LayoutItem::Stack(stack) => Node::Stack(stack.build()),
+                    LayoutItem::Spacer { size } => Node::Spacer(*size),
+                    LayoutItem::Widget(kind) => Node::Widget(kind.build()),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl LayoutConfig {
+    ///
+    /// Create the widgets and work out where they go
+    ///
+    pub fn build(&self) -> Result<Vec<Placement>, LayoutError> {
+        let width = match self.module {
+            ModuleSelector::Span => 18,
+            _ => 9,
+        };
+        let (x, y) = (self.x.min(width), self.y.min(34));
+        let area = Shape {
+            x: self.width.unwrap_or(width).min(width - x),
+            y: self.height.unwrap_or(34).min(34 - y),
+        };
+        self.stack.build().layout(x, y, area)
+    }
+}
+
+///
+/// The widget layout used when no configuration file is given
+///
+pub fn default_widgets() -> Vec<WidgetConfig> {
+    vec![
+        WidgetConfig {
+            kind: WidgetKind::Battery {
+                index: None,
+                mode: BatteryMode::Bar,
+            },
+            x: 0,
+            y: 0,
+            module: ModuleSelector::Left,
+        },
+        WidgetConfig {
+            kind: WidgetKind::Cpu {
+                merge_threads: false,
+                rows: None,
+                aggregate: CpuAggregate::Mean,
+            },
+            x: 0,
+            y: 5,
+            module: ModuleSelector::Left,
+        },
+        WidgetConfig {
+            kind: WidgetKind::Clock {
+                twelve_hour: false,
+                seconds: ClockSeconds::None,
+                mode: ClockMode::Time,
+                timezone: None,
+            },
+            x: 0,
+            y: 23,
+            module: ModuleSelector::Left,
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn widget(json: &str) -> WidgetConfig {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn widget_placement() {
+        let config = widget(
+            r#"{ "type": "clock", "x": 2, "y": 20, "module": "right", "twelve_hour": true }"#,
+        );
+        assert_eq!((config.x, config.y), (2, 20));
+        assert_eq!(config.module, ModuleSelector::Right);
+        assert!(matches!(
+            config.kind,
+            WidgetKind::Clock {
+                twelve_hour: true,
+                ..
+            }
+        ));
+
+        // Placement defaults to the top left of the left module
+        let config = widget(r#"{ "type": "battery" }"#);
+        assert_eq!((config.x, config.y), (0, 0));
+        assert_eq!(config.module, ModuleSelector::Left);
+    }
+
+    #[test]
+    fn module_selectors() {
+        let selector = |s: &str| ModuleSelector::from(s.to_string());
+        assert_eq!(selector("left"), ModuleSelector::Left);
+        assert_eq!(selector("Right"), ModuleSelector::Right);
+        assert_eq!(selector("SPAN"), ModuleSelector::Span);
+        assert_eq!(
+            selector("/dev/ttyACM0"),
+            ModuleSelector::Device("/dev/ttyACM0".to_string())
+        );
+        // Serial numbers keep their case
+        assert_eq!(
+            selector("FRAKDEBZ0100000000"),
+            ModuleSelector::Device("FRAKDEBZ0100000000".to_string())
+        );
+
+        let config = widget(r#"{ "type": "cpu", "module": "span" }"#);
+        assert_eq!(config.module, ModuleSelector::Span);
+    }
+
+    #[test]
+    fn defaults() {
+        let config: Config = serde_json::from_str("{}").unwrap();
+        assert!(config.rate.is_none());
+        assert!(config.widgets.is_none());
+        assert!(config.layouts.is_none());
+        assert!(config.alert.is_none());
+
+        match widget(r#"{ "type": "disk" }"#).kind {
+            WidgetKind::Disk {
+                mounts,
+                warn_pct,
+                bar_height,
+            } => {
+                assert!(mounts.is_none());
+                assert_eq!(warn_pct, 90.0);
+                assert_eq!(bar_height, 2);
+            }
+            _ => panic!("not a disk widget"),
+        }
+
+        match widget(r#"{ "type": "text", "text": "hi" }"#).kind {
+            WidgetKind::Text {
+                font,
+                rotated,
+                scroll,
+                length,
+                ..
+            } => {
+                assert_eq!(font, FontChoice::default());
+                assert!(!rotated);
+                assert_eq!(scroll, 1);
+                assert!(length.is_none());
+            }
+            _ => panic!("not a text widget"),
+        }
+
+        let alert: AlertConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(alert.threshold, 10.0);
+        assert_eq!(alert.style, AlertStyle::Flash);
+        assert_eq!(alert.cycles, 3);
+        assert!(alert.charging);
+    }
+
+    #[test]
+    fn unknown_fields() {
+        let typo = serde_json::from_str::<Config>(r#"{ "rate": 1.0, "brightnes": 20 }"#);
+        let err = typo.err().unwrap().to_string();
+        assert!(err.contains("brightnes"), "{}", err);
+
+        assert!(serde_json::from_str::<ModuleSides>(r#"{ "lef": "A" }"#).is_err());
+        assert!(serde_json::from_str::<AlertConfig>(r#"{ "cycle": 2 }"#).is_err());
+
+        // Widget and layout options are checked too, next to the placement
+        let typo = serde_json::from_str::<WidgetConfig>(
+            r#"{ "type": "cpu", "x": 1, "merge_thread": true }"#,
+        );
+        let err = typo.err().unwrap().to_string();
+        assert!(err.contains("merge_thread"), "{}", err);
+        let typo = r#"{ "type": "disk", "module": "right", "warnpct": 80 }"#;
+        assert!(serde_json::from_str::<WidgetConfig>(typo).is_err());
+        let typo = r#"{ "x": 2, "spaceing": 1, "items": [] }"#;
+        assert!(serde_json::from_str::<LayoutConfig>(typo).is_err());
+        let typo = r#"{ "items": [{ "type": "stack", "items": [], "algn": "end" }] }"#;
+        assert!(serde_json::from_str::<LayoutConfig>(typo).is_err());
+    }
+
+    /// Name of a widget, and the name of every option it has
+    fn fields(kind: &WidgetKind) -> (&'static str, Vec<&'static str>) {
+        // No `..` here, so a new option can't be added without listing it
+        match kind {
+            WidgetKind::Battery { index: _, mode: _ } => ("battery", vec!["index", "mode"]),
+            WidgetKind::Cpu {
+                merge_threads: _,
+                rows: _,
+                aggregate: _,
+            } => ("cpu", vec!["merge_threads", "rows", "aggregate"]),
+            WidgetKind::Clock {
+                twelve_hour: _,
+                seconds: _,
+                mode: _,
+                timezone: _,
+            } => ("clock", vec!["twelve_hour", "seconds", "mode", "timezone"]),
+            WidgetKind::Memory {
+                style: _,
+                swap: _,
+                width: _,
+                height: _,
+            } => ("memory", vec!["style", "swap", "width", "height"]),
+            WidgetKind::Disk {
+                mounts: _,
+                warn_pct: _,
+                bar_height: _,
+            } => ("disk", vec!["mounts", "warn_pct", "bar_height"]),
+            WidgetKind::Network {
+                interface: _,
+                width: _,
+                height: _,
+            } => ("network", vec!["interface", "width", "height"]),
+            WidgetKind::CpuHistory {
+                percent: _,
+                width: _,
+                height: _,
+            } => ("cpu_history", vec!["percent", "width", "height"]),
+            WidgetKind::Temperature {
+                sensor: _,
+                warn: _,
+                crit: _,
+                fan: _,
+                height: _,
+            } => (
+                "temperature",
+                vec!["sensor", "warn", "crit", "fan", "height"],
+            ),
+            WidgetKind::Text {
+                text: _,
+                font: _,
+                font_file: _,
+                font_height: _,
+                rotated: _,
+                length: _,
+                scroll: _,
+            } => (
+                "text",
+                vec![
+                    "text",
+                    "font",
+                    "font_file",
+                    "font_height",
+                    "rotated",
+                    "length",
+                    "scroll",
+                ],
+            ),
+            WidgetKind::Image {
+                path: _,
+                width: _,
+                height: _,
+                dither: _,
+            } => ("image", vec!["path", "width", "height", "dither"]),
+        }
+    }
+
+    #[test]
+    fn every_option_is_listed() {
+        let infos = crate::widget::all_widgets();
+        let mut seen = vec![];
+
+        for info in &infos {
+            // Fill in the options that have no default
+            let mut json = json!({ "type": info.name });
+            for option in ["text", "path"] {
+                if info.options.iter().any(|o| o.name == option) {
+                    json[option] = json!("");
+                }
+            }
+            let kind: WidgetKind = serde_json::from_value(json).unwrap();
+
+            let (name, options) = fields(&kind);
+            assert_eq!(name, info.name);
+            for option in options {
+                assert!(
+                    info.options.iter().any(|o| o.name == option),
+                    "{} has no {} option",
+                    name,
+                    option
+                );
+            }
+            seen.push(name);
+        }
+
+        // And every kind of widget is listed
+        assert_eq!(seen.len(), 10);
+    }
+
+    #[test]
+    fn rates() {
+        assert_eq!(check_rate(0.5).unwrap(), 0.5);
+        assert_eq!(check_rate(5.5).unwrap(), 5.5);
+        for rate in [0.0, -1.0, 5.6, f64::NAN, f64::INFINITY] {
+            assert!(check_rate(rate).is_err(), "{}", rate);
+        }
+    }
+}
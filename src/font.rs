@@ -0,0 +1,1039 @@
+use std::{collections::HashMap, fmt, fs, path::Path, sync::OnceLock};
+
+use crate::widget::{OFF, ON_DIM, ON_FULL};
+
+///
+/// A rectangle of LED brightnesses, stored row by row
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bitmap {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Bitmap {
+    pub fn new(width: usize, height: usize) -> Bitmap {
+        Bitmap {
+            width,
+            height,
+            pixels: vec![OFF; width * height],
+        }
+    }
+
+    ///
+    /// Build a bitmap from rows of bits, the highest of `width` bits being the leftmost pixel
+    ///
+    fn from_rows(width: usize, rows: &[u8]) -> Bitmap {
+        let pixels = rows
+            .iter()
+            .flat_map(|row| (0..width).map(move |x| row >> (width - 1 - x) & 1))
+            .map(|bit| if bit == 1 { ON_FULL } else { OFF })
+            .collect();
+
+        Bitmap {
+            width,
+            height: rows.len(),
+            pixels,
+        }
+    }
+
+    ///
+    /// Build a bitmap from columns of bits, the lowest bit being the top pixel
+    ///
+    fn from_columns(height: usize, columns: &[u8]) -> Bitmap {
+        let mut bitmap = Bitmap::new(columns.len(), height);
+        for (x, col) in columns.iter().enumerate() {
+            for y in 0..height {
+                if col >> y & 1 == 1 {
+                    bitmap.pixels[y * bitmap.width + x] = ON_FULL;
+                }
+            }
+        }
+        bitmap
+    }
+
+    ///
+    /// Build a bitmap from packed rows of bits, as stored in font files. Every row
+    /// starts on a new byte, the highest bit being the leftmost pixel.
+    ///
+    fn from_packed(width: usize, height: usize, data: &[u8]) -> Bitmap {
+        let row_bytes = width.div_ceil(8);
+        let mut bitmap = Bitmap::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let byte = data.get(y * row_bytes + x / 8).copied().unwrap_or(0);
+                if byte >> (7 - x % 8) & 1 == 1 {
+                    bitmap.pixels[y * width + x] = ON_FULL;
+                }
+            }
+        }
+        bitmap
+    }
+
+    ///
+    /// Resize to the given size. Each pixel takes the brightest of the pixels it
+    /// covers, so thin lines don't disappear when shrinking.
+    ///
+    fn scaled(&self, width: usize, height: usize) -> Bitmap {
+        let mut out = Bitmap::new(width, height);
+        for y in 0..height {
+            let rows = y * self.height / height..((y + 1) * self.height).div_ceil(height);
+            for x in 0..width {
+                let cols = x * self.width / width..((x + 1) * self.width).div_ceil(width);
+                out.pixels[y * width + x] = rows
+                    .clone()
+                    .flat_map(|sy| cols.clone().map(move |sx| (sx, sy)))
+                    .map(|(sx, sy)| self.get(sx, sy))
+                    .max()
+                    .unwrap_or(OFF);
+            }
+        }
+        out
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+
+    ///
+    /// Copy another bitmap into this one at the given position. Parts that fall
+    /// outside of this bitmap are cut off.
+    ///
+    pub fn blit(&mut self, src: &Bitmap, x: isize, y: isize) {
+        for sy in 0..src.height {
+            for sx in 0..src.width {
+                let (dx, dy) = (x + sx as isize, y + sy as isize);
+                if dx < 0 || dy < 0 || dx >= self.width as isize || dy >= self.height as isize {
+                    continue;
+                }
+                self.pixels[dy as usize * self.width + dx as usize] = src.get(sx, sy);
+            }
+        }
+    }
+
+    ///
+    /// Rotate a quarter turn clockwise, so the top of the bitmap faces right
+    ///
+    pub fn rotate_cw(&self) -> Bitmap {
+        let mut out = Bitmap::new(self.height, self.width);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                out.pixels[x * out.width + (self.height - 1 - y)] = self.get(x, y);
+            }
+        }
+        out
+    }
+
+    ///
+    /// Lower every lit pixel to the dim brightness
+    ///
+    pub fn dimmed(mut self) -> Bitmap {
+        for led in self.pixels.iter_mut() {
+            *led = (*led).min(ON_DIM);
+        }
+        self
+    }
+}
+
+///
+/// A monospaced bitmap font. Text is laid out left to right, or top to bottom for
+/// rotated fonts, with one empty LED between glyphs.
+///
+#[derive(Clone)]
+pub struct Font {
+    /// Size of each glyph, before rotation
+    pub width: usize,
+    pub height: usize,
+    glyphs: HashMap<char, Bitmap>,
+    rotated: bool,
+}
+
+impl Font {
+    ///
+    /// Built-in 3x5 font. Lowercase letters are drawn as uppercase ones.
+    ///
+    pub fn small() -> &'static Font {
+        static SMALL: OnceLock<Font> = OnceLock::new();
+        SMALL.get_or_init(|| {
+            let mut glyphs: HashMap<char, Bitmap> = FONT_3X5
+                .iter()
+                .map(|(c, rows)| (*c, Bitmap::from_rows(3, rows)))
+                .collect();
+            for (num, digit) in DIGITS.iter().enumerate() {
+                let c = char::from_digit(num as u32, 10).unwrap();
+                let pixels = digit.to_vec();
+                glyphs.insert(
+                    c,
+                    Bitmap {
+                        width: 3,
+                        height: 5,
+                        pixels,
+                    },
+                );
+            }
+
+            Font {
+                width: 3,
+                height: 5,
+                glyphs,
+                rotated: false,
+            }
+        })
+    }
+
+    ///
+    /// Built-in 5x7 font, covering all of printable ASCII
+    ///
+    pub fn large() -> &'static Font {
+        static LARGE: OnceLock<Font> = OnceLock::new();
+        LARGE.get_or_init(|| Font {
+            width: 5,
+            height: 7,
+            glyphs: (' '..='~')
+                .zip(FONT_5X7.iter())
+                .map(|(c, cols)| (c, Bitmap::from_columns(7, cols)))
+                .collect(),
+            rotated: false,
+        })
+    }
+
+    ///
+    /// The same font turned a quarter turn clockwise, for text running down the
+    /// length of a module
+    ///
+    pub fn rotated(&self) -> Font {
+        Font {
+            width: self.width,
+            height: self.height,
+            glyphs: self
+                .glyphs
+                .iter()
+                .map(|(c, glyph)| (*c, glyph.rotate_cw()))
+                .collect(),
+            rotated: !self.rotated,
+        }
+    }
+
+    pub fn is_rotated(&self) -> bool {
+        self.rotated
+    }
+
+    ///
+    /// The glyph for a character. Falls back to the uppercase letter, then to '?'
+    ///
+    pub fn glyph(&self, c: char) -> Option<&Bitmap> {
+        self.glyphs
+            .get(&c)
+            .or_else(|| self.glyphs.get(&c.to_ascii_uppercase()))
+            .or_else(|| self.glyphs.get(&'?'))
+    }
+
+    ///
+    /// Draw a line of text. Characters are drawn as in glyph(), so a missing character
+    /// shows up as its uppercase letter or '?'. Fonts loaded from a file have already
+    /// been filled in with the built-in 3x5 font.
+    ///
+    pub fn render(&self, text: &str) -> Bitmap {
+        let count = text.chars().count();
+        let length = (count * (self.width + 1)).saturating_sub(1);
+        let mut out = match self.rotated {
+            false => Bitmap::new(length, self.height),
+            true => Bitmap::new(self.height, length),
+        };
+
+        for (idx, c) in text.chars().enumerate() {
+            let Some(glyph) = self.glyph(c) else {
+                continue;
+            };
+            let pos = (idx * (self.width + 1)) as isize;
+            match self.rotated {
+                false => out.blit(glyph, pos, 0),
+                true => out.blit(glyph, 0, pos),
+            }
+        }
+
+        out
+    }
+}
+
+// ================ Font Files ================
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xB5, 0x4A, 0x86];
+/// Largest glyph size and offset accepted from a BDF font. Anything bigger is
+/// far larger than a module, and most likely a corrupt file.
+const BDF_MAX_SIZE: isize = 256;
+
+#[derive(Debug)]
+pub enum FontError {
+    Io(std::io::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FontError::Io(e) => write!(f, "Unable to read font file: {}", e),
+            FontError::Invalid(e) => write!(f, "Invalid font file: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FontError {}
+
+fn invalid(reason: &str) -> FontError {
+    FontError::Invalid(reason.to_string())
+}
+
+impl Font {
+    ///
+    /// Load a BDF, PSF1 or PSF2 bitmap font. The glyphs are scaled to `height` if
+    /// given, and made narrower if needed so a glyph fits on a module.
+    /// Characters missing from the file are drawn with the built-in 3x5 font.
+    ///
+    pub fn load(path: &Path, height: Option<usize>) -> Result<Font, FontError> {
+        let data = fs::read(path).map_err(FontError::Io)?;
+        Font::parse(&data, height)
+    }
+
+    ///
+    /// Parse the contents of a font file, see load()
+    ///
+    pub fn parse(data: &[u8], height: Option<usize>) -> Result<Font, FontError> {
+        let font = if data.starts_with(&PSF1_MAGIC) {
+            Font::parse_psf1(data)?
+        } else if data.starts_with(&PSF2_MAGIC) {
+            Font::parse_psf2(data)?
+        } else if data.starts_with(b"STARTFONT") {
+            let text = std::str::from_utf8(data).map_err(|_| invalid("BDF font is not UTF-8"))?;
+            Font::parse_bdf(text)?
+        } else {
+            return Err(invalid("not a BDF, PSF1 or PSF2 font"));
+        };
+
+        if font.glyphs.is_empty() {
+            return Err(invalid("font has no glyphs"));
+        }
+        Ok(font.scaled(height).with_fallback(Font::small()))
+    }
+
+    ///
+    /// Scale every glyph to the given height, keeping their proportions but
+    /// never wider than a module
+    ///
+    fn scaled(self, height: Option<usize>) -> Font {
+        let mut height = height.unwrap_or(self.height).clamp(1, 34);
+        let mut width = ((self.width * height + self.height / 2) / self.height).max(1);
+        if width > 9 {
+            height = ((height * 9 + width / 2) / width).max(1);
+            width = 9;
+        }
+        if (width, height) == (self.width, self.height) {
+            return self;
+        }
+
+        Font {
+            width,
+            height,
+            glyphs: self
+                .glyphs
+                .iter()
+                .map(|(c, glyph)| (*c, glyph.scaled(width, height)))
+                .collect(),
+            rotated: self.rotated,
+        }
+    }
+
+    ///
+    /// Add the glyphs of another font for characters this one doesn't have,
+    /// centered in this font's glyph size
+    ///
+    fn with_fallback(mut self, fallback: &Font) -> Font {
+        for (c, glyph) in fallback.glyphs.iter() {
+            if self.glyphs.contains_key(c) {
+                continue;
+            }
+            let mut cell = Bitmap::new(self.width, self.height);
+            let x = (self.width as isize - glyph.width as isize) / 2;
+            let y = (self.height as isize - glyph.height as isize) / 2;
+            cell.blit(glyph, x, y);
+            self.glyphs.insert(*c, cell);
+        }
+        self
+    }
+
+    ///
+    /// A font whose glyphs are looked up by position. `table` lists the characters
+    /// each glyph is used for, without it glyph N is character N.
+    ///
+    fn from_indexed(
+        width: usize,
+        height: usize,
+        bitmaps: Vec<Bitmap>,
+        table: Option<Vec<Vec<char>>>,
+    ) -> Font {
+        let mut glyphs = HashMap::new();
+        for (idx, bitmap) in bitmaps.into_iter().enumerate() {
+            let chars: Vec<char> = match &table {
+                Some(table) => table.get(idx).cloned().unwrap_or_default(),
+                None => char::from_u32(idx as u32).into_iter().collect(),
+            };
+            for c in chars {
+                glyphs.entry(c).or_insert_with(|| bitmap.clone());
+            }
+        }
+
+        Font {
+            width,
+            height,
+            glyphs,
+            rotated: false,
+        }
+    }
+
+    ///
+    /// Linux console font, version 1. Glyphs are always 8 pixels wide.
+    ///
+    fn parse_psf1(data: &[u8]) -> Result<Font, FontError> {
+        let header = data
+            .get(..4)
+            .ok_or_else(|| invalid("PSF1 header is cut short"))?;
+        let (mode, height) = (header[2], header[3] as usize);
+        if height == 0 {
+            return Err(invalid("PSF1 glyphs have no height"));
+        }
+
+        let count = if mode & 0x01 != 0 { 512 } else { 256 };
+        let end = 4 + count * height;
+        let glyphs = data
+            .get(4..end)
+            .ok_or_else(|| invalid("PSF1 glyphs are cut short"))?;
+        let bitmaps = glyphs
+            .chunks(height)
+            .map(|rows| Bitmap::from_packed(8, height, rows))
+            .collect();
+
+        // Each glyph's characters are 16 bit values ended by 0xFFFF, with
+        // combining sequences (which aren't supported) after a 0xFFFE
+        let table = (mode & 0x06 != 0).then(|| {
+            let values: Vec<u16> = data[end..]
+                .chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect();
+            values
+                .split(|&v| v == 0xFFFF)
+                .take(count)
+                .map(|entry| {
+                    entry
+                        .iter()
+                        .take_while(|&&v| v != 0xFFFE)
+                        .filter_map(|&v| char::from_u32(v as u32))
+                        .collect()
+                })
+                .collect()
+        });
+
+        Ok(Font::from_indexed(8, height, bitmaps, table))
+    }
+
+    ///
+    /// Linux console font, version 2
+    ///
+    fn parse_psf2(data: &[u8]) -> Result<Font, FontError> {
+        let field = |idx: usize| {
+            data.get(idx * 4..idx * 4 + 4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+                .ok_or_else(|| invalid("PSF2 header is cut short"))
+        };
+        let (header_size, flags, count) = (field(2)?, field(3)?, field(4)?);
+        let (glyph_size, height, width) = (field(5)?, field(6)?, field(7)?);
+        if width == 0 || height == 0 || glyph_size < width.div_ceil(8) * height {
+            return Err(invalid(
+                "PSF2 glyph size doesn't match its width and height",
+            ));
+        }
+
+        let end = count
+            .checked_mul(glyph_size)
+            .and_then(|size| size.checked_add(header_size))
+            .ok_or_else(|| invalid("PSF2 glyphs are cut short"))?;
+        let glyphs = data
+            .get(header_size..end)
+            .ok_or_else(|| invalid("PSF2 glyphs are cut short"))?;
+        let bitmaps = glyphs
+            .chunks(glyph_size)
+            .map(|glyph| Bitmap::from_packed(width, height, glyph))
+            .collect();
+
+        // Each glyph's characters are UTF-8 ended by 0xFF, with combining
+        // sequences (which aren't supported) after a 0xFE
+        let table = (flags & 0x01 != 0).then(|| {
+            data[end..]
+                .split(|&b| b == 0xFF)
+                .take(count)
+                .map(|entry| {
+                    let singles = entry.split(|&b| b == 0xFE).next().unwrap_or_default();
+                    std::str::from_utf8(singles)
+                        .map(|s| s.chars().collect())
+                        .unwrap_or_default()
+                })
+                .collect()
+        });
+
+        Ok(Font::from_indexed(width, height, bitmaps, table))
+    }
+
+    ///
+    /// X11 Glyph Bitmap Distribution Format. Every glyph is placed in the font's
+    /// bounding box, so proportional fonts become monospaced.
+    ///
+    fn parse_bdf(text: &str) -> Result<Font, FontError> {
+        fn numbers<const N: usize>(words: std::str::SplitWhitespace) -> Option<[isize; N]> {
+            let values: Vec<isize> = words.map(|w| w.parse().ok()).collect::<Option<_>>()?;
+            values.try_into().ok()
+        }
+        fn hex_digit(digit: u8) -> u8 {
+            (digit as char).to_digit(16).unwrap_or(0) as u8
+        }
+        // Width, height and the x/y offsets of a bounding box
+        fn bounding_box(words: std::str::SplitWhitespace) -> Result<[isize; 4], FontError> {
+            let bounds =
+                numbers::<4>(words).ok_or_else(|| invalid("BDF bounding box is invalid"))?;
+            let [w, h, x, y] = bounds;
+            if !(0..=BDF_MAX_SIZE).contains(&w)
+                || !(0..=BDF_MAX_SIZE).contains(&h)
+                || x.abs() > BDF_MAX_SIZE
+                || y.abs() > BDF_MAX_SIZE
+            {
+                return Err(invalid("BDF bounding box is too large"));
+            }
+            Ok(bounds)
+        }
+
+        let mut bounds: Option<[isize; 4]> = None;
+        let mut glyphs = HashMap::new();
+        let mut lines = text.lines().map(str::trim);
+
+        while let Some(line) = lines.next() {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("FONTBOUNDINGBOX") => bounds = Some(bounding_box(words)?),
+                Some("STARTCHAR") => {
+                    let [font_w, font_h, font_x, font_y] =
+                        bounds.ok_or_else(|| invalid("BDF font has no FONTBOUNDINGBOX"))?;
+
+                    let (mut encoding, mut bbx, mut rows) = (None, None, vec![]);
+                    let mut in_bitmap = false;
+                    for line in lines.by_ref() {
+                        let mut words = line.split_whitespace();
+                        match words.next() {
+                            Some("ENDCHAR") => break,
+                            Some("ENCODING") => {
+                                encoding = words.next().and_then(|w| w.parse().ok())
+                            }
+                            Some("BBX") => bbx = Some(bounding_box(words)?),
+                            Some("BITMAP") => in_bitmap = true,
+                            Some(row) if in_bitmap => rows.push(row),
+                            _ => {}
+                        }
+                    }
+
+                    // Glyphs without a character (ENCODING -1) can't be used
+                    let Some(c) = encoding.and_then(|n: u32| char::from_u32(n)) else {
+                        continue;
+                    };
+                    let [w, h, x, y] = bbx.ok_or_else(|| invalid("BDF glyph has no BBX"))?;
+                    let (w, h) = (w.max(0) as usize, h.max(0) as usize);
+
+                    let row_bytes = w.div_ceil(8);
+                    let mut packed = vec![];
+                    for row in rows.iter().take(h) {
+                        if !row.bytes().all(|b| b.is_ascii_hexdigit()) {
+                            return Err(invalid("BDF bitmap isn't hexadecimal"));
+                        }
+                        let mut bytes: Vec<u8> = row
+                            .as_bytes()
+                            .chunks_exact(2)
+                            .map(|pair| hex_digit(pair[0]) << 4 | hex_digit(pair[1]))
+                            .collect();
+                        bytes.resize(row_bytes, 0);
+                        packed.extend(bytes);
+                    }
+
+                    // BBX offsets are from the bottom left, relative to the baseline
+                    let mut cell = Bitmap::new(font_w.max(1) as usize, font_h.max(1) as usize);
+                    let top = (font_h + font_y) - (h as isize + y);
+                    cell.blit(&Bitmap::from_packed(w, h, &packed), x - font_x, top);
+                    glyphs.insert(c, cell);
+                }
+                _ => {}
+            }
+        }
+
+        let [width, height, _, _] =
+            bounds.ok_or_else(|| invalid("BDF font has no FONTBOUNDINGBOX"))?;
+        Ok(Font {
+            width: width.max(1) as usize,
+            height: height.max(1) as usize,
+            glyphs,
+            rotated: false,
+        })
+    }
+}
+
+// ================ Glyphs ================
+/// Digits of the 3x5 font, with dimmed pixels to smooth out the curves
+const DIGITS: [[u8; 15]; 10] = [
+    [
+        OFF, ON_FULL, OFF, ON_FULL, OFF, ON_FULL, ON_FULL, OFF, ON_FULL, ON_FULL, OFF, ON_FULL,
+        OFF, ON_FULL, OFF,
+    ],
+    [
+        OFF, OFF, ON_FULL, OFF, ON_DIM, ON_FULL, OFF, OFF, ON_FULL, OFF, OFF, ON_FULL, OFF, OFF,
+        ON_FULL,
+    ],
+    [
+        ON_FULL, ON_FULL, ON_FULL, OFF, OFF, ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF, OFF,
+        ON_FULL, ON_FULL, ON_FULL,
+    ],
+    [
+        ON_FULL, ON_FULL, ON_FULL, OFF, OFF, ON_FULL, ON_FULL, ON_FULL, OFF, OFF, OFF, ON_FULL,
+        ON_FULL, ON_FULL, ON_FULL,
+    ],
+    [
+        ON_FULL, OFF, ON_FULL, ON_FULL, OFF, ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF, OFF, ON_FULL,
+        OFF, OFF, ON_FULL,
+    ],
+    [
+        ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF, OFF, ON_FULL, ON_FULL, ON_FULL, OFF, OFF, ON_FULL,
+        ON_FULL, ON_FULL, ON_FULL,
+    ],
+    [
+        OFF, ON_FULL, ON_DIM, ON_FULL, OFF, OFF, ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF, ON_FULL,
+        ON_FULL, ON_FULL, ON_FULL,
+    ],
+    [
+        ON_FULL, ON_FULL, ON_FULL, ON_DIM, OFF, ON_FULL, OFF, OFF, ON_FULL, OFF, ON_FULL, OFF, OFF,
+        ON_FULL, OFF,
+    ],
+    [
+        ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF, ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF,
+        ON_FULL, ON_FULL, ON_FULL, ON_FULL,
+    ],
+    [
+        ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF, ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF, OFF,
+        ON_FULL, ON_DIM, ON_FULL, OFF,
+    ],
+];
+
+/// Everything but the digits and lowercase letters of the 3x5 font, one row of
+/// 3 bits per byte
+const FONT_3X5: [(char, [u8; 5]); 59] = [
+    (' ', [0b000, 0b000, 0b000, 0b000, 0b000]),
+    ('!', [0b010, 0b010, 0b010, 0b000, 0b010]),
+    ('"', [0b101, 0b101, 0b000, 0b000, 0b000]),
+    ('#', [0b101, 0b111, 0b101, 0b111, 0b101]),
+    ('$', [0b011, 0b110, 0b010, 0b011, 0b110]),
+    ('%', [0b101, 0b001, 0b010, 0b100, 0b101]),
+    ('&', [0b010, 0b101, 0b010, 0b101, 0b011]),
+    ('\'', [0b010, 0b010, 0b000, 0b000, 0b000]),
+    ('(', [0b001, 0b010, 0b010, 0b010, 0b001]),
+    (')', [0b100, 0b010, 0b010, 0b010, 0b100]),
+    ('*', [0b000, 0b101, 0b010, 0b101, 0b000]),
+    ('+', [0b000, 0b010, 0b111, 0b010, 0b000]),
+    (',', [0b000, 0b000, 0b000, 0b010, 0b100]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    (';', [0b000, 0b010, 0b000, 0b010, 0b100]),
+    ('<', [0b001, 0b010, 0b100, 0b010, 0b001]),
+    ('=', [0b000, 0b111, 0b000, 0b111, 0b000]),
+    ('>', [0b100, 0b010, 0b001, 0b010, 0b100]),
+    ('?', [0b111, 0b001, 0b010, 0b000, 0b010]),
+    ('@', [0b010, 0b101, 0b111, 0b100, 0b011]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    ('[', [0b011, 0b010, 0b010, 0b010, 0b011]),
+    ('\\', [0b100, 0b100, 0b010, 0b001, 0b001]),
+    (']', [0b110, 0b010, 0b010, 0b010, 0b110]),
+    ('^', [0b010, 0b101, 0b000, 0b000, 0b000]),
+    ('_', [0b000, 0b000, 0b000, 0b000, 0b111]),
+    ('`', [0b100, 0b010, 0b000, 0b000, 0b000]),
+    ('{', [0b011, 0b010, 0b110, 0b010, 0b011]),
+    ('|', [0b010, 0b010, 0b010, 0b010, 0b010]),
+    ('}', [0b110, 0b010, 0b011, 0b010, 0b110]),
+    ('~', [0b000, 0b011, 0b110, 0b000, 0b000]),
+];
+
+/// Printable ASCII (' ' to '~') in the classic 5x7 font, one column of 7 bits per
+/// byte with the lowest bit at the top
+const FONT_5X7: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5F, 0x00, 0x00], // !
+    [0x00, 0x07, 0x00, 0x07, 0x00], // "
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], // #
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], // $
+    [0x23, 0x13, 0x08, 0x64, 0x62], // %
+    [0x36, 0x49, 0x55, 0x22, 0x50], // &
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '
+    [0x00, 0x1C, 0x22, 0x41, 0x00], // (
+    [0x00, 0x41, 0x22, 0x1C, 0x00], // )
+    [0x08, 0x2A, 0x1C, 0x2A, 0x08], // *
+    [0x08, 0x08, 0x3E, 0x08, 0x08], // +
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ,
+    [0x08, 0x08, 0x08, 0x08, 0x08], // -
+    [0x00, 0x60, 0x60, 0x00, 0x00], // .
+    [0x20, 0x10, 0x08, 0x04, 0x02], // /
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], // 0
+    [0x00, 0x42, 0x7F, 0x40, 0x00], // 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x45, 0x4B, 0x31], // 3
+    [0x18, 0x14, 0x12, 0x7F, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3C, 0x4A, 0x49, 0x49, 0x30], // 6
+    [0x01, 0x71, 0x09, 0x05, 0x03], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x06, 0x49, 0x49, 0x29, 0x1E], // 9
+    [0x00, 0x36, 0x36, 0x00, 0x00], // :
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ;
+    [0x08, 0x14, 0x22, 0x41, 0x00], // <
+    [0x14, 0x14, 0x14, 0x14, 0x14], // =
+    [0x00, 0x41, 0x22, 0x14, 0x08], // >
+    [0x02, 0x01, 0x51, 0x09, 0x06], // ?
+    [0x32, 0x49, 0x79, 0x41, 0x3E], // @
+    [0x7E, 0x11, 0x11, 0x11, 0x7E], // A
+    [0x7F, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3E, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], // D
+    [0x7F, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7F, 0x09, 0x09, 0x09, 0x01], // F
+    [0x3E, 0x41, 0x49, 0x49, 0x7A], // G
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], // H
+    [0x00, 0x41, 0x7F, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3F, 0x01], // J
+    [0x7F, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7F, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7F, 0x02, 0x0C, 0x02, 0x7F], // M
+    [0x7F, 0x04, 0x08, 0x10, 0x7F], // N
+    [0x3E, 0x41, 0x41, 0x41, 0x3E], // O
+    [0x7F, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3E, 0x41, 0x51, 0x21, 0x5E], // Q
+    [0x7F, 0x09, 0x19, 0x29, 0x46], // R
+    [0x46, 0x49, 0x49, 0x49, 0x31], // S
+    [0x01, 0x01, 0x7F, 0x01, 0x01], // T
+    [0x3F, 0x40, 0x40, 0x40, 0x3F], // U
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], // V
+    [0x3F, 0x40, 0x38, 0x40, 0x3F], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x07, 0x08, 0x70, 0x08, 0x07], // Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], // Z
+    [0x00, 0x7F, 0x41, 0x41, 0x00], // [
+    [0x02, 0x04, 0x08, 0x10, 0x20], // \
+    [0x00, 0x41, 0x41, 0x7F, 0x00], // ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], // _
+    [0x00, 0x01, 0x02, 0x04, 0x00], // `
+    [0x20, 0x54, 0x54, 0x54, 0x78], // a
+    [0x7F, 0x48, 0x44, 0x44, 0x38], // b
+    [0x38, 0x44, 0x44, 0x44, 0x20], // c
+    [0x38, 0x44, 0x44, 0x48, 0x7F], // d
+    [0x38, 0x54, 0x54, 0x54, 0x18], // e
+    [0x08, 0x7E, 0x09, 0x01, 0x02], // f
+    [0x0C, 0x52, 0x52, 0x52, 0x3E], // g
+    [0x7F, 0x08, 0x04, 0x04, 0x78], // h
+    [0x00, 0x44, 0x7D, 0x40, 0x00], // i
+    [0x20, 0x40, 0x44, 0x3D, 0x00], // j
+    [0x7F, 0x10, 0x28, 0x44, 0x00], // k
+    [0x00, 0x41, 0x7F, 0x40, 0x00], // l
+    [0x7C, 0x04, 0x18, 0x04, 0x78], // m
+    [0x7C, 0x08, 0x04, 0x04, 0x78], // n
+    [0x38, 0x44, 0x44, 0x44, 0x38], // o
+    [0x7C, 0x14, 0x14, 0x14, 0x08], // p
+    [0x08, 0x14, 0x14, 0x18, 0x7C], // q
+    [0x7C, 0x08, 0x04, 0x04, 0x08], // r
+    [0x48, 0x54, 0x54, 0x54, 0x20], // s
+    [0x04, 0x3F, 0x44, 0x40, 0x20], // t
+    [0x3C, 0x40, 0x40, 0x20, 0x7C], // u
+    [0x1C, 0x20, 0x40, 0x20, 0x1C], // v
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], // w
+    [0x44, 0x28, 0x10, 0x28, 0x44], // x
+    [0x0C, 0x50, 0x50, 0x50, 0x3C], // y
+    [0x44, 0x64, 0x54, 0x4C, 0x44], // z
+    [0x00, 0x08, 0x36, 0x41, 0x00], // {
+    [0x00, 0x00, 0x7F, 0x00, 0x00], // |
+    [0x00, 0x41, 0x36, 0x08, 0x00], // }
+    [0x08, 0x04, 0x08, 0x10, 0x08], // ~
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Draw a bitmap as text, '#' for lit and '.' for off
+    fn ascii(bitmap: &Bitmap) -> Vec<String> {
+        bitmap
+            .pixels
+            .chunks(bitmap.width)
+            .map(|row| {
+                row.iter()
+                    .map(|&led| if led == OFF { '.' } else { '#' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn small_font() {
+        let font = Font::small();
+        assert_eq!(
+            ascii(font.glyph('A').unwrap()),
+            [".#.", "#.#", "###", "#.#", "#.#"]
+        );
+        // Lowercase falls back to uppercase, anything else to '?'
+        assert_eq!(font.glyph('a'), font.glyph('A'));
+        assert_eq!(font.glyph('é'), font.glyph('?'));
+        // Digits keep their dimmed pixels
+        assert_eq!(font.glyph('1').unwrap().get(1, 1), ON_DIM);
+
+        for c in ' '..='~' {
+            let glyph = font.glyph(c).unwrap();
+            assert_eq!((glyph.width, glyph.height), (3, 5), "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn large_font() {
+        let font = Font::large();
+        assert_eq!(
+            ascii(font.glyph('E').unwrap()),
+            ["#####", "#....", "#....", "####.", "#....", "#....", "#####"]
+        );
+        assert_ne!(font.glyph('e'), font.glyph('E'));
+        assert_eq!(font.glyph('\u{2603}'), font.glyph('?'));
+    }
+
+    #[test]
+    fn render_text() {
+        let text = Font::small().render("HI!");
+        assert_eq!(
+            ascii(&text),
+            [
+                "#.#.###..#.",
+                "#.#..#...#.",
+                "###..#...#.",
+                "#.#..#.....",
+                "#.#.###..#.",
+            ]
+        );
+        assert_eq!(Font::small().render("").width, 0);
+    }
+
+    #[test]
+    fn rotated_text() {
+        let font = Font::small().rotated();
+        assert!(font.is_rotated());
+
+        let text = font.render("L-");
+        assert_eq!((text.width, text.height), (5, 7));
+        assert_eq!(
+            ascii(&text),
+            ["#####", "#....", "#....", ".....", "..#..", "..#..", "..#.."]
+        );
+        assert_eq!(text, Font::small().render("L-").rotate_cw());
+    }
+
+    #[test]
+    fn blit_clips() {
+        let mut canvas = Bitmap::new(4, 2);
+        let glyph = Font::small().glyph('-').unwrap();
+        canvas.blit(glyph, -1, -2);
+        assert_eq!(ascii(&canvas), ["##..", "...."]);
+        canvas.blit(glyph, 2, -1);
+        assert_eq!(ascii(&canvas), ["##..", "..##"]);
+    }
+
+    const BDF: &str = "STARTFONT 2.1
+FONT -test-tiny
+SIZE 6 75 75
+FONTBOUNDINGBOX 4 6 0 -1
+CHARS 2
+STARTCHAR T
+ENCODING 84
+BBX 3 5 0 0
+BITMAP
+E0
+40
+40
+40
+40
+ENDCHAR
+STARTCHAR comma
+ENCODING 44
+BBX 1 2 1 -1
+BITMAP
+80
+80
+ENDCHAR
+ENDFONT
+";
+
+    #[test]
+    fn bdf_font() {
+        let font = Font::parse(BDF.as_bytes(), None).unwrap();
+        assert_eq!((font.width, font.height), (4, 6));
+        assert_eq!(
+            ascii(font.glyph('T').unwrap()),
+            ["###.", ".#..", ".#..", ".#..", ".#..", "...."]
+        );
+        // Hangs below the baseline
+        assert_eq!(
+            ascii(font.glyph(',').unwrap()),
+            ["....", "....", "....", "....", ".#..", ".#.."]
+        );
+    }
+
+    #[test]
+    fn missing_glyphs_use_builtin_font() {
+        let font = Font::parse(BDF.as_bytes(), None).unwrap();
+        // The 3x5 'A', centered
+        assert_eq!(
+            ascii(font.glyph('A').unwrap()),
+            [".#..", "#.#.", "###.", "#.#.", "#.#.", "...."]
+        );
+        assert_eq!(font.glyph('\u{2603}'), font.glyph('?'));
+    }
+
+    /// A PSF1 font with 256 glyphs, each a single lit row at the glyph's index % 4
+    fn psf1(table: bool) -> Vec<u8> {
+        let mut data = vec![0x36, 0x04, if table { 0x02 } else { 0x00 }, 4];
+        for idx in 0..256 {
+            let mut glyph = [0u8; 4];
+            glyph[idx % 4] = 0xFF;
+            data.extend(glyph);
+        }
+        if table {
+            // Glyph 0 is used for 'a' and 'b', glyph 1 for 'é' and a combining sequence
+            data.extend([b'a', 0, b'b', 0, 0xFF, 0xFF]);
+            data.extend([0xE9, 0, 0xFE, 0xFF, b'e', 0, 0x01, 0x03, 0xFF, 0xFF]);
+        }
+        data
+    }
+
+    #[test]
+    fn psf1_font() {
+        let font = Font::parse(&psf1(false), None).unwrap();
+        assert_eq!((font.width, font.height), (8, 4));
+        // Without a table, glyph 0x41 is 'A'
+        assert_eq!(
+            ascii(font.glyph('A').unwrap()),
+            ["........", "########", "........", "........"]
+        );
+
+        let font = Font::parse(&psf1(true), None).unwrap();
+        assert_eq!(font.glyph('a'), font.glyph('b'));
+        assert_eq!(ascii(font.glyph('é').unwrap())[1], "########");
+        // 'e' only appears in a sequence, so it comes from the built-in font
+        assert_eq!(font.glyph('e'), font.glyph('E'));
+        assert_eq!(font.glyph('E').unwrap().get(0, 0), OFF);
+    }
+
+    #[test]
+    fn psf2_font() {
+        let mut data = PSF2_MAGIC.to_vec();
+        // version, header size, flags (has table), glyphs, glyph size, height, width
+        for field in [0u32, 32, 1, 2, 6, 3, 10] {
+            data.extend(field.to_le_bytes());
+        }
+        data.extend([0xFF, 0xC0, 0x00, 0x00, 0x80, 0x40]);
+        data.extend([0x00, 0x00, 0xFF, 0xC0, 0x00, 0x00]);
+        data.extend("x\u{2014}\u{FF}".as_bytes());
+        data.push(0xFF);
+        data.extend("-".as_bytes());
+        data.push(0xFF);
+
+        // 10 wide is too wide for a module, so it is narrowed to 9
+        let font = Font::parse(&data, None).unwrap();
+        assert_eq!((font.width, font.height), (9, 3));
+        assert_eq!(font.glyph('x'), font.glyph('\u{2014}'));
+        assert_eq!(ascii(font.glyph('-').unwrap())[1], "#########");
+        assert_eq!(font.glyph('ÿ'), font.glyph('x'));
+
+        // Cut short
+        assert!(Font::parse(&data[..40], None).is_err());
+    }
+
+    #[test]
+    fn scaled_font() {
+        let font = Font::parse(BDF.as_bytes(), Some(3)).unwrap();
+        assert_eq!((font.width, font.height), (2, 3));
+        assert_eq!(ascii(font.glyph('T').unwrap()), ["##", "#.", "#."]);
+    }
+
+    #[test]
+    fn invalid_font() {
+        assert!(matches!(
+            Font::parse(b"not a font", None),
+            Err(FontError::Invalid(_))
+        ));
+        assert!(matches!(
+            Font::load(Path::new("/nonexistent.psf"), None),
+            Err(FontError::Io(_))
+        ));
+        assert!(Font::parse(b"STARTFONT 2.1\nENDFONT\n", None).is_err());
+    }
+
+    #[test]
+    fn bdf_bitmap_not_hex() {
+        let bdf = BDF.replacen("E0", "0É", 1);
+        assert!(matches!(
+            Font::parse(bdf.as_bytes(), None),
+            Err(FontError::Invalid(_))
+        ));
+        let bdf = BDF.replacen("E0", "G0", 1);
+        assert!(Font::parse(bdf.as_bytes(), None).is_err());
+    }
+
+    #[test]
+    fn bdf_sizes_are_bounded() {
+        let huge_font = BDF.replace(
+            "FONTBOUNDINGBOX 4 6 0 -1",
+            "FONTBOUNDINGBOX 100000 100000 0 0",
+        );
+        assert!(matches!(
+            Font::parse(huge_font.as_bytes(), None),
+            Err(FontError::Invalid(_))
+        ));
+
+        let huge_glyph = BDF.replace("BBX 3 5 0 0", "BBX 3 999999999 0 0");
+        assert!(Font::parse(huge_glyph.as_bytes(), None).is_err());
+        let huge_offset = BDF.replace("BBX 3 5 0 0", "BBX 3 5 0 -9223372036854775807");
+        assert!(Font::parse(huge_offset.as_bytes(), None).is_err());
+        let negative = BDF.replace("BBX 3 5 0 0", "BBX -3 5 0 0");
+        assert!(Font::parse(negative.as_bytes(), None).is_err());
+    }
+}
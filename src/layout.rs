@@ -0,0 +1,391 @@
+use std::fmt;
+
+use serde::Deserialize;
+
+use crate::widget::{Shape, UpdatableWidget};
+
+///
+/// Which way a stack places its items
+///
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// Top to bottom
+    #[default]
+    Vertical,
+    /// Left to right
+    Horizontal,
+}
+
+impl Direction {
+    /// Length along the stack, and across it
+    fn split(self, shape: Shape) -> (usize, usize) {
+        match self {
+            Direction::Vertical => (shape.y, shape.x),
+            Direction::Horizontal => (shape.x, shape.y),
+        }
+    }
+
+    fn join(self, along: usize, across: usize) -> Shape {
+        match self {
+            Direction::Vertical => Shape {
+                x: across,
+                y: along,
+            },
+            Direction::Horizontal => Shape {
+                x: along,
+                y: across,
+            },
+        }
+    }
+}
+
+///
+/// Where items go when there is more room than they need. Top and left are the
+/// same as start, bottom and right the same as end.
+///
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Align {
+    #[default]
+    #[serde(alias = "top", alias = "left")]
+    Start,
+    Center,
+    #[serde(alias = "bottom", alias = "right")]
+    End,
+}
+
+impl Align {
+    /// Offset of something `used` long in `room`
+    fn offset(self, room: usize, used: usize) -> usize {
+        let free = room.saturating_sub(used);
+        match self {
+            Align::Start => 0,
+            Align::Center => free / 2,
+            Align::End => free,
+        }
+    }
+}
+
+///
+/// A part of a layout
+///
+pub enum Node {
+    Widget(Box<dyn UpdatableWidget>),
+    /// Empty space, this many LEDs along the stack
+    Spacer(usize),
+    Stack(Stack),
+}
+
+impl Node {
+    fn size(&self, direction: Direction, min: bool) -> Shape {
+        match self {
+            Node::Widget(widget) if min => widget.min_size(),
+            Node::Widget(widget) => widget.preferred_size(),
+            Node::Spacer(len) => direction.join(*len, 0),
+            Node::Stack(stack) => stack.size(min),
+        }
+    }
+}
+
+///
+/// Items placed one after another, top to bottom or left to right. Items are
+/// drawn at their preferred size, and shrunk down to their minimum size when
+/// there isn't room for that.
+///
+pub struct Stack {
+    pub direction: Direction,
+    /// Where the items go along the stack, when they don't fill it
+    pub align: Align,
+    /// Where each item goes across the stack, when it's narrower than the stack
+    pub cross_align: Align,
+    /// Empty LEDs around the items
+    pub padding: usize,
+    /// Empty LEDs between the items
+    pub spacing: usize,
+    pub items: Vec<Node>,
+}
+
+///
+/// A widget, and the area the layout gave it
+///
+pub struct Placement {
+    pub widget: Box<dyn UpdatableWidget>,
+    pub x: usize,
+    pub y: usize,
+    pub size: Shape,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct LayoutError {
+    /// Smallest size the layout can be drawn at
+    pub needed: Shape,
+    pub available: Shape,
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Layout doesn't fit: it needs at least {}x{} LEDs, but only {}x{} are available",
+            self.needed.x, self.needed.y, self.available.x, self.available.y
+        )
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+impl Stack {
+    fn gaps(&self) -> usize {
+        self.spacing * self.items.len().saturating_sub(1)
+    }
+
+    ///
+    /// Preferred (or minimum) size of the whole stack, padding included
+    ///
+    fn size(&self, min: bool) -> Shape {
+        let (along, across) = self
+            .items
+            .iter()
+            .map(|item| self.direction.split(item.size(self.direction, min)))
+            .fold((0, 0), |(along, across), (a, c)| (along + a, across.max(c)));
+
+        self.direction.join(
+            along + self.gaps() + self.padding * 2,
+            across + self.padding * 2,
+        )
+    }
+
+    ///
+    /// Work out where every widget goes in an area of a module, starting at (x, y).
+    /// Fails if the items don't fit even at their minimum size.
+    ///
+    pub fn layout(self, x: usize, y: usize, size: Shape) -> Result<Vec<Placement>, LayoutError> {
+        let needed = self.size(true);
+        if needed.x > size.x || needed.y > size.y {
+            return Err(LayoutError {
+                needed,
+                available: size,
+            });
+        }
+
+        let mut placements = vec![];
+        self.place(x, y, size, &mut placements);
+        Ok(placements)
+    }
+
+    fn place(self, x: usize, y: usize, size: Shape, out: &mut Vec<Placement>) {
+        let dir = self.direction;
+        let (room_along, room_across) = dir.split(Shape {
+            x: size.x.saturating_sub(self.padding * 2),
+            y: size.y.saturating_sub(self.padding * 2),
+        });
+
+        let preferred: Vec<(usize, usize)> = self
+            .items
+            .iter()
+            .map(|item| dir.split(item.size(dir, false)))
+            .collect();
+        let min: Vec<usize> = self
+            .items
+            .iter()
+            .map(|item| dir.split(item.size(dir, true)).0)
+            .collect();
+
+        // Take one LED at a time from every item that can still shrink, so they
+        // shrink evenly, until everything fits
+        let mut lengths: Vec<usize> = preferred.iter().map(|&(along, _)| along).collect();
+        let gaps = self.gaps();
+        let mut excess = (lengths.iter().sum::<usize>() + gaps).saturating_sub(room_along);
+        while excess > 0 {
+            let before = excess;
+            for (length, &min) in lengths.iter_mut().zip(min.iter()) {
+                if excess > 0 && *length > min {
+                    *length -= 1;
+                    excess -= 1;
+                }
+            }
+            if excess == before {
+                break;
+            }
+        }
+
+        let used = lengths.iter().sum::<usize>() + gaps;
+        let mut pos = self.align.offset(room_along, used);
+        for ((item, length), (_, across)) in self.items.into_iter().zip(lengths).zip(preferred) {
+            let across = across.min(room_across);
+            let start = dir.join(pos, self.cross_align.offset(room_across, across));
+            let (ix, iy) = (x + self.padding + start.x, y + self.padding + start.y);
+            let size = dir.join(length, across);
+
+            match item {
+                Node::Widget(widget) => out.push(Placement {
+                    widget,
+                    x: ix,
+                    y: iy,
+                    size,
+                }),
+                Node::Spacer(_) => {}
+                Node::Stack(stack) => stack.place(ix, iy, size, out),
+            }
+
+            pos += length + self.spacing;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::LayoutConfig, mock::MockWidget};
+
+    fn widget(x: usize, y: usize) -> Node {
+        Node::Widget(Box::new(MockWidget::new(Shape { x, y }, Shape { x, y })))
+    }
+
+    fn flexible(preferred: Shape, min: Shape) -> Node {
+        Node::Widget(Box::new(MockWidget::new(preferred, min)))
+    }
+
+    fn stack(direction: Direction, items: Vec<Node>) -> Stack {
+        Stack {
+            direction,
+            align: Align::Start,
+            cross_align: Align::Start,
+            padding: 0,
+            spacing: 0,
+            items,
+        }
+    }
+
+    const MODULE: Shape = Shape { x: 9, y: 34 };
+
+    /// Position and size of every placed widget
+    fn areas(placements: &[Placement]) -> Vec<(usize, usize, usize, usize)> {
+        placements
+            .iter()
+            .map(|p| (p.x, p.y, p.size.x, p.size.y))
+            .collect()
+    }
+
+    #[test]
+    fn vertical_stack() {
+        let mut column = stack(
+            Direction::Vertical,
+            vec![widget(9, 4), Node::Spacer(2), widget(9, 11), widget(5, 5)],
+        );
+        column.spacing = 1;
+
+        let placed = column.layout(0, 0, MODULE).unwrap();
+        assert_eq!(areas(&placed), [(0, 0, 9, 4), (0, 8, 9, 11), (0, 20, 5, 5)]);
+    }
+
+    #[test]
+    fn alignment() {
+        let aligned = |align: Align, cross_align: Align| {
+            let mut column = stack(Direction::Vertical, vec![widget(9, 4), widget(5, 6)]);
+            column.align = align;
+            column.cross_align = cross_align;
+            areas(&column.layout(0, 0, MODULE).unwrap())
+        };
+
+        assert_eq!(
+            aligned(Align::Center, Align::Start),
+            [(0, 12, 9, 4), (0, 16, 5, 6)]
+        );
+        assert_eq!(
+            aligned(Align::End, Align::Center),
+            [(0, 24, 9, 4), (2, 28, 5, 6)]
+        );
+        assert_eq!(
+            aligned(Align::Start, Align::End),
+            [(0, 0, 9, 4), (4, 4, 5, 6)]
+        );
+    }
+
+    #[test]
+    fn nested_stacks_with_padding() {
+        let mut row = stack(Direction::Horizontal, vec![widget(3, 5), widget(3, 3)]);
+        row.spacing = 1;
+        row.cross_align = Align::Center;
+
+        let mut column = stack(
+            Direction::Vertical,
+            vec![widget(7, 4), Node::Stack(row), widget(7, 2)],
+        );
+        column.padding = 1;
+        assert_eq!(column.size(false), Shape { x: 9, y: 13 });
+
+        // In an area starting at (0, 10)
+        let placed = column.layout(0, 10, Shape { x: 9, y: 24 }).unwrap();
+        assert_eq!(
+            areas(&placed),
+            [(1, 11, 7, 4), (1, 15, 3, 5), (5, 16, 3, 3), (1, 20, 7, 2)]
+        );
+    }
+
+    #[test]
+    fn shrinks_to_fit() {
+        let graph = || flexible(Shape { x: 9, y: 20 }, Shape { x: 1, y: 5 });
+        let column = stack(Direction::Vertical, vec![widget(9, 4), graph(), graph()]);
+
+        // 44 LEDs tall, 10 too many, taken evenly from both graphs
+        let placed = column.layout(0, 0, MODULE).unwrap();
+        assert_eq!(
+            areas(&placed),
+            [(0, 0, 9, 4), (0, 4, 9, 15), (0, 19, 9, 15)]
+        );
+    }
+
+    #[test]
+    fn does_not_fit() {
+        let column = stack(
+            Direction::Vertical,
+            vec![widget(9, 20), Node::Spacer(4), widget(9, 11)],
+        );
+        let err = column.layout(0, 0, MODULE).err().unwrap();
+        assert_eq!(
+            err,
+            LayoutError {
+                needed: Shape { x: 9, y: 35 },
+                available: MODULE,
+            }
+        );
+
+        let row = stack(Direction::Horizontal, vec![widget(5, 5), widget(5, 5)]);
+        assert!(row.layout(0, 0, MODULE).is_err());
+    }
+
+    #[test]
+    fn layout_from_config() {
+        let config: LayoutConfig = serde_json::from_str(
+            r#"{
+                "module": "right",
+                "y": 2,
+                "align": "bottom",
+                "spacing": 1,
+                "items": [
+                    { "type": "clock" },
+                    { "type": "spacer", "size": 3 },
+                    { "type": "stack", "direction": "horizontal", "items": [
+                        { "type": "text", "text": "AB", "length": 4 },
+                        { "type": "text", "text": "C", "length": 3 }
+                    ]}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let placed = config.build().unwrap();
+        assert_eq!(
+            areas(&placed),
+            [(0, 13, 9, 11), (0, 29, 4, 5), (4, 29, 3, 5)]
+        );
+
+        let too_tall: LayoutConfig = serde_json::from_str(
+            r#"{ "items": [{ "type": "clock" }, { "type": "clock" }, { "type": "clock" }, { "type": "clock" }] }"#,
+        )
+        .unwrap();
+        assert!(too_tall.build().is_err());
+    }
+}
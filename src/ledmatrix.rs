@@ -1,27 +1,246 @@
 #![allow(dead_code)]
-use crate::matrix;
+use crate::{
+    matrix,
+    transport::{PortEnumerator, SystemPorts, Transport},
+};
+use serde::Serialize;
 use serialport::{SerialPortInfo, SerialPortType};
 use std::{
+    fmt, io,
+    io::{Read, Write},
     thread,
-    time::{Duration, SystemTime},
+    time::{Duration, Instant},
 };
 
-const BRIGHTNESS_CMD: u8 = 0x00;
-const PATTERN_CMD: u8 = 0x01;
-const BOOTLOADER_CMD: u8 = 0x02;
-const SLEEP_CMD: u8 = 0x03;
-const ANIMATE_CMD: u8 = 0x04;
-const PANIC_CMD: u8 = 0x05;
-const DRAW_CMD: u8 = 0x06;
-const SET_COL: u8 = 0x07;
-const COMMIT_COL: u8 = 0x08;
+pub(crate) const BRIGHTNESS_CMD: u8 = 0x00;
+pub(crate) const PATTERN_CMD: u8 = 0x01;
+pub(crate) const BOOTLOADER_CMD: u8 = 0x02;
+pub(crate) const SLEEP_CMD: u8 = 0x03;
+pub(crate) const ANIMATE_CMD: u8 = 0x04;
+pub(crate) const PANIC_CMD: u8 = 0x05;
+pub(crate) const DRAW_CMD: u8 = 0x06;
+pub(crate) const SET_COL: u8 = 0x07;
+pub(crate) const COMMIT_COL: u8 = 0x08;
+
+pub(crate) const CHECKFW_CMD: u8 = 0x20;
+
+pub(crate) const CMD_START: [u8; 2] = [0x32, 0xAC];
+
+/// Every response from the firmware is padded to this many bytes
+pub(crate) const RESPONSE_SIZE: usize = 32;
+
+/// USB vendor & product ID of the LED matrix module
+pub const LEDMATRIX_VID: u16 = 12972;
+pub const LEDMATRIX_PID: u16 = 32;
+
+///
+/// Everything that can go wrong while talking to an LED matrix module
+///
+#[derive(Debug)]
+pub enum LedMatrixError {
+    /// The serial ports on the system could not be listed
+    Enumerate(serialport::Error),
+    /// The serial port of the module could not be opened
+    PortOpen(serialport::Error),
+    /// A command could not be sent to the module
+    Write(io::Error),
+    /// A response could not be read back from the module
+    Read(io::Error),
+    /// The module did not respond before the timeout
+    Timeout,
+    /// The module responded with fewer bytes than expected
+    ShortRead { expected: usize, got: usize },
+    /// The module was unplugged, or the system suspended it
+    DeviceGone,
+    /// A destructive command was sent without being confirmed
+    Unconfirmed,
+    /// The module firmware is older than MIN_FW_VERSION
+    UnsupportedFirmware(FirmwareVersion),
+}
 
-const CHECKFW_CMD: u8 = 0x20;
+impl LedMatrixError {
+    ///
+    /// Wrap an IO error, recognizing the errors that mean the device has disappeared
+    ///
+    fn from_io(err: io::Error, wrap: fn(io::Error) -> LedMatrixError) -> LedMatrixError {
+        // ENXIO (6) & ENODEV (19) are reported on linux after the USB device is removed
+        let gone = matches!(
+            err.kind(),
+            io::ErrorKind::BrokenPipe
+                | io::ErrorKind::NotConnected
+                | io::ErrorKind::NotFound
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::ConnectionReset
+        ) || matches!(err.raw_os_error(), Some(6) | Some(19));
+
+        if gone {
+            LedMatrixError::DeviceGone
+        } else {
+            wrap(err)
+        }
+    }
+}
 
-const CMD_START: [u8; 2] = [0x32, 0xAC];
+impl fmt::Display for LedMatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LedMatrixError::Enumerate(e) => write!(f, "Unable to list serial ports: {}", e),
+            LedMatrixError::PortOpen(e) => write!(f, "Failed to open serial port: {}", e),
+            LedMatrixError::Write(e) => write!(f, "Failed to send command: {}", e),
+            LedMatrixError::Read(e) => write!(f, "Failed to read from module: {}", e),
+            LedMatrixError::Timeout => write!(f, "Serial read timed out"),
+            LedMatrixError::ShortRead { expected, got } => {
+                write!(f, "Expected {} bytes from module, got {}", expected, got)
+            }
+            LedMatrixError::DeviceGone => write!(f, "Module is no longer connected"),
+            LedMatrixError::Unconfirmed => write!(f, "Command must be confirmed before sending"),
+            LedMatrixError::UnsupportedFirmware(v) => write!(
+                f,
+                "Firmware {} is too old, at least {} is required",
+                v, MIN_FW_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LedMatrixError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LedMatrixError::Enumerate(e) | LedMatrixError::PortOpen(e) => Some(e),
+            LedMatrixError::Write(e) | LedMatrixError::Read(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+///
+/// Firmware version reported by a module. Pre-releases are ordered before the
+/// release with the same version number.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct FirmwareVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
+    pub pre_release: bool,
+}
+
+/// Oldest firmware known to support everything we use - drawing columns of LEDs
+/// with individual brightness (SET_COL/COMMIT_COL), and reading back state
+pub const MIN_FW_VERSION: FirmwareVersion = FirmwareVersion::new(0, 1, 7, false);
+
+impl FirmwareVersion {
+    pub const fn new(major: u8, minor: u8, patch: u8, pre_release: bool) -> FirmwareVersion {
+        FirmwareVersion {
+            major,
+            minor,
+            patch,
+            pre_release,
+        }
+    }
+
+    ///
+    /// Decode the response to CHECKFW_CMD - the major version, minor & patch
+    /// packed into one byte, and a pre-release flag
+    ///
+    fn from_bytes(bytes: &[u8]) -> FirmwareVersion {
+        FirmwareVersion {
+            major: bytes[0],
+            minor: (bytes[1] & 0xF0) >> 4,
+            patch: bytes[1] & 0x0F,
+            pre_release: bytes[2] == 1,
+        }
+    }
+
+    ///
+    /// Whether this firmware supports everything we use
+    ///
+    pub fn is_supported(&self) -> bool {
+        *self >= MIN_FW_VERSION
+    }
+}
+
+impl Ord for FirmwareVersion {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.major, self.minor, self.patch, !self.pre_release).cmp(&(
+            other.major,
+            other.minor,
+            other.patch,
+            !other.pre_release,
+        ))
+    }
+}
+
+impl PartialOrd for FirmwareVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if self.pre_release {
+            write!(f, "-pre")?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// Identifying information about a connected module
+///
+#[derive(Serialize)]
+pub struct ModuleInfo {
+    pub port_name: String,
+    pub serial_number: Option<String>,
+    pub vid: u16,
+    pub pid: u16,
+    /// None if the module didn't respond to the version check
+    pub firmware_version: Option<FirmwareVersion>,
+    pub firmware_supported: Option<bool>,
+}
+
+///
+/// Patterns built into the LED matrix firmware
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pattern {
+    /// Light up the given percentage of the matrix, from the bottom
+    Percentage(u8),
+    Gradient,
+    DoubleGradient,
+    /// The Framework "Lotus" logo, sideways
+    LotusHorizontal,
+    ZigZag,
+    /// Every LED on at full brightness
+    FullBrightness,
+    /// The word "PANIC"
+    Panic,
+    /// The Framework "Lotus" logo, upright
+    LotusVertical,
+}
+
+impl Pattern {
+    ///
+    /// Parameters for PATTERN_CMD - the pattern ID, followed by any pattern arguments
+    ///
+    fn params(&self) -> Vec<u8> {
+        match self {
+            Pattern::Percentage(pct) => vec![0x00, (*pct).min(100)],
+            Pattern::Gradient => vec![0x01],
+            Pattern::DoubleGradient => vec![0x02],
+            Pattern::LotusHorizontal => vec![0x03],
+            Pattern::ZigZag => vec![0x04],
+            Pattern::FullBrightness => vec![0x05],
+            Pattern::Panic => vec![0x06],
+            Pattern::LotusVertical => vec![0x07],
+        }
+    }
+}
 
 pub struct LedMatrix {
-    port: Box<dyn serialport::SerialPort>,
+    port: Box<dyn Transport>,
     pub port_info: SerialPortInfo,
 }
 
@@ -30,103 +249,167 @@ impl LedMatrix {
     /// Find LED matricies connected to the laptop.
     /// Searches for serial ports connected with the LED matrix' product ID & vendor ID
     ///
-    pub fn detect() -> Vec<LedMatrix> {
-        let sports = serialport::available_ports().expect("No ports found!");
-
-        // Loop through all available serial ports, save ports that match the LED matrix product name
-        let mut found_ledmat: Vec<SerialPortInfo> = vec![];
-        for ref sp in sports {
-            // println!("{:?}", sp.port_type);
-            match sp.port_type {
-                SerialPortType::UsbPort(ref info) => {
-                    let info_c = info.clone();
-                    if info_c.vid == 12972 && info_c.pid == 32 {
-                        found_ledmat.push(sp.clone());
-                    }
-                }
-                _ => {}
-            }
-        }
+    pub fn detect() -> Result<Vec<LedMatrix>, LedMatrixError> {
+        LedMatrix::detect_with(&SystemPorts)
+    }
 
-        if found_ledmat.len() <= 0 {
-            println!("No LED matrix modules found.");
-            return vec![]
-        }
+    ///
+    /// Find and connect to LED matricies using the given serial port enumerator
+    ///
+    pub fn detect_with(ports: &dyn PortEnumerator) -> Result<Vec<LedMatrix>, LedMatrixError> {
+        let found_ledmat = LedMatrix::find_ports(ports)?;
 
+        // A module that can't be opened is skipped, so the others can still be used.
+        // Reported on stderr to keep --list-modules output parseable.
         let mut mats: Vec<LedMatrix> = Vec::new();
         for m in found_ledmat {
-            mats.push(LedMatrix::new(m));
+            match LedMatrix::open(ports, m.clone()) {
+                Ok(mat) => mats.push(mat),
+                Err(e) => eprintln!("{} - {}", m.port_name, e),
+            }
         }
 
-        println!("Found LED matrix modules:");
-        for i in mats.iter_mut() {
-            println!(
-                "{} - {}",
-                i.port_info.port_name.to_string(),
-                i.get_fw_version()
-            );
-        }
+        Ok(mats)
+    }
+
+    ///
+    /// List the serial ports that belong to LED matrix modules, without opening them
+    ///
+    pub fn find_ports(ports: &dyn PortEnumerator) -> Result<Vec<SerialPortInfo>, LedMatrixError> {
+        let sports = ports.available_ports()?;
+
+        // Loop through all available serial ports, save ports that match the LED matrix product name
+        let found_ledmat = sports
+            .into_iter()
+            .filter(|sp| match sp.port_type {
+                SerialPortType::UsbPort(ref info) => {
+                    info.vid == LEDMATRIX_VID && info.pid == LEDMATRIX_PID
+                }
+                _ => false,
+            })
+            .collect();
 
-        mats
+        Ok(found_ledmat)
     }
 
     ///
     /// Creates and connects to an LED matrix
     ///
-    pub fn new(portinfo: SerialPortInfo) -> LedMatrix {
-        let port0builder = serialport::new(portinfo.port_name.to_string(), 115_200);
-        let port0 = port0builder.open().expect("Failed to open serial port");
+    pub fn new(portinfo: SerialPortInfo) -> Result<LedMatrix, LedMatrixError> {
+        LedMatrix::open(&SystemPorts, portinfo)
+    }
+
+    ///
+    /// Connects to an LED matrix through the given serial port enumerator
+    ///
+    pub fn open(
+        ports: &dyn PortEnumerator,
+        portinfo: SerialPortInfo,
+    ) -> Result<LedMatrix, LedMatrixError> {
+        let port0 = ports.open(&portinfo)?;
 
+        Ok(LedMatrix::with_transport(port0, portinfo))
+    }
+
+    ///
+    /// Creates an LED matrix that communicates over an already opened transport
+    ///
+    pub fn with_transport(port: Box<dyn Transport>, portinfo: SerialPortInfo) -> LedMatrix {
         LedMatrix {
-            port: port0,
+            port,
             port_info: portinfo,
         }
     }
 
+    ///
+    /// Collect the port, USB IDs and firmware version of the module
+    ///
+    pub fn info(&mut self) -> ModuleInfo {
+        let (vid, pid) = match self.port_info.port_type {
+            SerialPortType::UsbPort(ref info) => (info.vid, info.pid),
+            _ => (0, 0),
+        };
+        let firmware_version = self.get_fw_version().ok();
+
+        ModuleInfo {
+            port_name: self.port_info.port_name.clone(),
+            serial_number: self.serial_number().map(str::to_string),
+            vid,
+            pid,
+            firmware_version,
+            firmware_supported: firmware_version.map(|v| v.is_supported()),
+        }
+    }
+
+    ///
+    /// USB serial number of the module, which stays the same across reboots and
+    /// reconnects (unlike the port name)
+    ///
+    pub fn serial_number(&self) -> Option<&str> {
+        match self.port_info.port_type {
+            SerialPortType::UsbPort(ref info) => info.serial_number.as_deref(),
+            _ => None,
+        }
+    }
+
     ///
     /// Send a command to the LED matrix module.
     /// 1. Send the bytes 0x32 0xAC to initiate a command
     /// 2. Send the command byte (as listed above)
     /// 3. Send further parameters for the command
     ///
-    pub fn sendcommand(&mut self, cmd: u8, params: Option<&[u8]>) {
+    pub fn sendcommand(&mut self, cmd: u8, params: Option<&[u8]>) -> Result<(), LedMatrixError> {
         let mut buffer: Vec<u8> = vec![];
         buffer.extend_from_slice(CMD_START.as_slice());
         buffer.push(cmd);
-        match params {
-            Some(p) => buffer.extend_from_slice(p),
-            None => {}
-        };
+        if let Some(p) = params {
+            buffer.extend_from_slice(p);
+        }
 
         self.port
-            .write(buffer.as_slice())
-            .expect("Failed to send command");
-        self.port.flush().unwrap();
+            .write_all(buffer.as_slice())
+            .and_then(|_| self.port.flush())
+            .map_err(|e| LedMatrixError::from_io(e, LedMatrixError::Write))
     }
 
     ///
     /// Read back a set amount of bytes from the serial port. Returns Err if
-    /// nothing is read and the port times out
+    /// the port times out before all of the bytes are read
     ///
     pub fn serialread(
         &mut self,
         numbytes: usize,
         timeout: Duration,
-    ) -> Result<Vec<u8>, &'static str> {
-        let start_t = SystemTime::now();
-
-        // Wait for bytes to be available
-        while self.port.bytes_to_read().unwrap() < 1 {
-            if start_t.elapsed().unwrap() > timeout {
-                return Err("Serial read timed out");
-            }
-            thread::sleep(Duration::from_millis(10));
-        }
-
+    ) -> Result<Vec<u8>, LedMatrixError> {
+        let start_t = Instant::now();
         let mut buffer: Vec<u8> = vec![0; numbytes];
+        let mut got = 0;
+
+        while got < numbytes {
+            let available = self
+                .port
+                .bytes_to_read()
+                .map_err(|e| LedMatrixError::from_io(e, LedMatrixError::Read))?;
+
+            // Wait for bytes to be available
+            if available < 1 {
+                if start_t.elapsed() > timeout {
+                    return Err(match got {
+                        0 => LedMatrixError::Timeout,
+                        _ => LedMatrixError::ShortRead {
+                            expected: numbytes,
+                            got,
+                        },
+                    });
+                }
+                thread::sleep(Duration::from_millis(10));
+                continue;
+            }
 
-        while self.port.bytes_to_read().unwrap() > 0 {
-            self.port.read(buffer.as_mut_slice()).unwrap();
+            got += self
+                .port
+                .read(&mut buffer[got..])
+                .map_err(|e| LedMatrixError::from_io(e, LedMatrixError::Read))?;
         }
 
         Ok(buffer)
@@ -135,37 +418,91 @@ impl LedMatrix {
     ///
     /// Get the current firmware version of the LED matrix module.
     ///
-    pub fn get_fw_version(&mut self) -> String {
-        self.sendcommand(CHECKFW_CMD, None);
-        let bytes = self
-            .serialread(32, Duration::from_secs(5))
-            .unwrap_or(vec![0]);
-        if bytes.len() < 3 {
-            return "".to_string();
-        }
+    pub fn get_fw_version(&mut self) -> Result<FirmwareVersion, LedMatrixError> {
+        self.sendcommand(CHECKFW_CMD, None)?;
+        let bytes = self.serialread(RESPONSE_SIZE, Duration::from_secs(5))?;
 
-        let major = bytes[0];
-        let minor = (bytes[1] & 0xF0) >> 4;
-        let patch = bytes[1] & 0x0F;
-        let pre_release = bytes[2] == 1;
+        Ok(FirmwareVersion::from_bytes(&bytes))
+    }
+
+    ///
+    /// Send a command without parameters, which makes the firmware report the
+    /// current value of that setting instead of changing it
+    ///
+    fn query(&mut self, cmd: u8) -> Result<u8, LedMatrixError> {
+        self.sendcommand(cmd, None)?;
+        let bytes = self.serialread(RESPONSE_SIZE, Duration::from_secs(1))?;
+
+        Ok(bytes[0])
+    }
+
+    ///
+    /// Get the brightness of the module (0=OFF, 255=FULL), which may have been
+    /// changed by another program
+    ///
+    pub fn get_brightness(&mut self) -> Result<u8, LedMatrixError> {
+        self.query(BRIGHTNESS_CMD)
+    }
 
-        let version = format!("{}.{}.{} Pre Release: {}", major, minor, patch, pre_release);
+    ///
+    /// Check whether the module is asleep
+    ///
+    pub fn is_sleeping(&mut self) -> Result<bool, LedMatrixError> {
+        Ok(self.query(SLEEP_CMD)? != 0)
+    }
 
-        version
+    ///
+    /// Check whether the firmware is animating (scrolling) the current image
+    ///
+    pub fn is_animating(&mut self) -> Result<bool, LedMatrixError> {
+        Ok(self.query(ANIMATE_CMD)? != 0)
     }
 
     ///
     /// Tell the module to wake up
     ///
-    pub fn wake(&mut self) {
-        self.sendcommand(SLEEP_CMD, Some(&[0]));
+    pub fn wake(&mut self) -> Result<(), LedMatrixError> {
+        self.sendcommand(SLEEP_CMD, Some(&[0]))
     }
 
     ///
     /// Tell the module to go to sleep
     ///
-    pub fn sleep(&mut self) {
-        self.sendcommand(SLEEP_CMD, Some(&[1]));
+    pub fn sleep(&mut self) -> Result<(), LedMatrixError> {
+        self.sendcommand(SLEEP_CMD, Some(&[1]))
+    }
+
+    ///
+    /// Display one of the patterns built into the firmware
+    ///
+    pub fn pattern(&mut self, pattern: Pattern) -> Result<(), LedMatrixError> {
+        self.sendcommand(PATTERN_CMD, Some(pattern.params().as_slice()))
+    }
+
+    ///
+    /// Start or stop the firmware scrolling the current image
+    ///
+    pub fn animate(&mut self, enable: bool) -> Result<(), LedMatrixError> {
+        self.sendcommand(ANIMATE_CMD, Some(&[enable as u8]))
+    }
+
+    ///
+    /// Reboot the module into its bootloader, so new firmware can be flashed.
+    /// The module disconnects and stays blank until it is flashed or power cycled,
+    /// so this does nothing unless `confirmed` is true.
+    ///
+    pub fn enter_bootloader(&mut self, confirmed: bool) -> Result<(), LedMatrixError> {
+        if !confirmed {
+            return Err(LedMatrixError::Unconfirmed);
+        }
+        self.sendcommand(BOOTLOADER_CMD, None)
+    }
+
+    ///
+    /// Make the firmware crash, to test how the module and host recover
+    ///
+    pub fn panic(&mut self) -> Result<(), LedMatrixError> {
+        self.sendcommand(PANIC_CMD, None)
     }
 
     ///
@@ -176,16 +513,16 @@ impl LedMatrix {
     /// This allows for faster framerates than draw_matrix (with brightnesses) since its
     /// ~0.4% of the data (1/255)
     ///
-    pub fn draw_bool_matrix(&mut self, mat: [[bool; 9]; 34]) {
+    pub fn draw_bool_matrix(&mut self, mat: [[bool; 9]; 34]) -> Result<(), LedMatrixError> {
         let buffer = matrix::encode(mat);
-        self.sendcommand(DRAW_CMD, Some(buffer.as_slice()));
+        self.sendcommand(DRAW_CMD, Some(buffer.as_slice()))
     }
 
     ///
     /// Sets the brightness of every LED in the module (0=OFF, 255=FULL)
     ///
-    pub fn set_full_brightness(&mut self, val: u8) {
-        self.sendcommand(BRIGHTNESS_CMD, Some(&[val]));
+    pub fn set_full_brightness(&mut self, val: u8) -> Result<(), LedMatrixError> {
+        self.sendcommand(BRIGHTNESS_CMD, Some(&[val]))
     }
 
     ///
@@ -194,32 +531,32 @@ impl LedMatrix {
     /// Columns are not changed until the commit_col function is run (Allows you to
     /// write all the columns THEN display them at once)
     ///
-    pub fn set_col(&mut self, col: u8, arr: [u8; 34]) {
+    pub fn set_col(&mut self, col: u8, arr: [u8; 34]) -> Result<(), LedMatrixError> {
         let mut vec = vec![];
         vec.push(col);
         vec.extend_from_slice(arr.as_slice());
-        self.sendcommand(SET_COL, Some(vec.as_slice()));
+        self.sendcommand(SET_COL, Some(vec.as_slice()))
     }
 
     ///
     /// Tell the module to display all the LEDs written to with set_col
     ///
-    pub fn commit_col(&mut self) {
-        self.sendcommand(COMMIT_COL, Some(&[]));
+    pub fn commit_col(&mut self) -> Result<(), LedMatrixError> {
+        self.sendcommand(COMMIT_COL, Some(&[]))
     }
 
     ///
     /// Display an entire matrix with individual LED brightness values. Slow updating,
     /// but allows for more complex UIs
     ///
-    pub fn draw_matrix(&mut self, mat: [[u8; 9]; 34]) {
+    pub fn draw_matrix(&mut self, mat: [[u8; 9]; 34]) -> Result<(), LedMatrixError> {
         // Transpose array
         let tpose = matrix::transpose(mat);
 
         for i in 0..9 {
-            self.set_col(i, tpose[i as usize]);
+            self.set_col(i, tpose[i as usize])?;
         }
 
-        self.commit_col();
+        self.commit_col()
     }
 }
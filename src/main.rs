@@ -1,16 +1,37 @@
+mod alert;
+mod config;
+mod font;
+mod layout;
 mod ledmatrix;
 mod matrix;
+#[cfg(test)]
+mod mock;
+mod modules;
+mod power;
+mod transport;
 mod widget;
 use std::{
+    path::PathBuf,
     process::exit,
     thread,
     time::{Duration, Instant},
 };
 
-use clap::Parser;
-use ledmatrix::LedMatrix;
+use clap::{Parser, Subcommand, ValueEnum};
+use ledmatrix::{LedMatrix, Pattern, MIN_FW_VERSION};
 
-use crate::widget::{AllCPUsWidget, BatteryWidget, ClockWidget, UpdatableWidget};
+use crate::{
+    alert::Alert,
+    config::{AlertConfig, Config, LayoutConfig, ModuleSelector, ModuleSides, WidgetConfig},
+    matrix::{Canvas, Matrix},
+    modules::Modules,
+    power::SharedBatteries,
+    transport::SystemPorts,
+    widget::{Shape, UpdatableWidget},
+};
+
+/// How often to look for modules that have been plugged in or resumed
+const RESCAN_INTERVAL: Duration = Duration::from_secs(5);
 
 #[derive(Parser)]
 #[command(version, about, long_about=None)]
@@ -22,94 +43,505 @@ struct Cli {
 
     /// List all widgets available for placement
     #[arg(long)]
-    list_widgets: bool, 
+    list_widgets: bool,
+
+    /// Output format for --list-modules and --list-widgets
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
     // ======== Program Control ========
-    /// Refresh rate, in Hz
-    #[arg(long, default_value_t = 0.5)]
-    rate: f64,
+    /// Refresh rate, in Hz (overrides the config file) [default: 0.5]
+    #[arg(long)]
+    rate: Option<f64>,
 
     // #[arg(long)]
     // Start the background service updating the matrix
     // start: bool,
+    /// JSON config file path
+    #[arg(long)]
+    config: Option<PathBuf>,
 
-    // #[arg(long)]
-    // JSON config file path
-    // config: Option<String>,
+    // ======== Diagnostics ========
+    /// Module to send a diagnostic command to (left, right, a port name or serial
+    /// number). Sent to every module if not given.
+    #[arg(long, global = true)]
+    module: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+}
+
+// Diagnostic commands, sent straight to the module firmware
+#[derive(Subcommand)]
+enum Command {
+    /// Display one of the patterns built into the firmware
+    Pattern {
+        #[arg(value_enum)]
+        pattern: PatternArg,
+
+        /// How much of the matrix to fill for the percentage pattern
+        #[arg(long, default_value_t = 50)]
+        percent: u8,
+    },
+    /// Start or stop the firmware scrolling the current image
+    Animate {
+        #[arg(action = clap::ArgAction::Set)]
+        enable: bool,
+    },
+    /// Reboot the module into its bootloader to flash new firmware
+    Bootloader {
+        /// The module stays blank until it is flashed or power cycled, this must be
+        /// passed to confirm
+        #[arg(long)]
+        confirm: bool,
+    },
+    /// Make the module firmware crash
+    Panic,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum PatternArg {
+    Percentage,
+    Gradient,
+    DoubleGradient,
+    LotusHorizontal,
+    Zigzag,
+    FullBrightness,
+    Panic,
+    LotusVertical,
+}
+
+impl PatternArg {
+    fn to_pattern(self, percent: u8) -> Pattern {
+        match self {
+            PatternArg::Percentage => Pattern::Percentage(percent),
+            PatternArg::Gradient => Pattern::Gradient,
+            PatternArg::DoubleGradient => Pattern::DoubleGradient,
+            PatternArg::LotusHorizontal => Pattern::LotusHorizontal,
+            PatternArg::Zigzag => Pattern::ZigZag,
+            PatternArg::FullBrightness => Pattern::FullBrightness,
+            PatternArg::Panic => Pattern::Panic,
+            PatternArg::LotusVertical => Pattern::LotusVertical,
+        }
+    }
 }
 
 struct Settings {
-    rate: f64
+    rate: f64,
+    brightness: Option<u8>,
+    sides: ModuleSides,
+    widgets: Vec<WidgetConfig>,
+    layouts: Vec<LayoutConfig>,
+    alert: Option<AlertConfig>,
 }
 
-fn define_settings(args: Cli) -> Settings{
-    let settings = Settings { 
-        rate: args.rate
+fn define_settings(args: Cli) -> Settings {
+    let config = match args.config {
+        Some(path) => Config::load(&path).unwrap_or_else(|e| {
+            println!("{}", e);
+            exit(1);
+        }),
+        None => Config::default(),
     };
 
-    settings
+    let rate = config::check_rate(args.rate.or(config.rate).unwrap_or(0.5)).unwrap_or_else(|e| {
+        println!("{}", e);
+        exit(1);
+    });
+
+    Settings {
+        rate,
+        brightness: config.brightness,
+        sides: config.modules.unwrap_or_default(),
+        // The default layout is only used when nothing is placed at all
+        widgets: match (config.widgets, &config.layouts) {
+            (Some(widgets), _) => widgets,
+            (None, Some(_)) => vec![],
+            (None, None) => config::default_widgets(),
+        },
+        layouts: config.layouts.unwrap_or_default(),
+        alert: config.alert,
+    }
+}
+
+///
+/// Find which of the connected modules a widget should be drawn on
+///
+fn select_module(
+    mats: &[LedMatrix],
+    selector: &ModuleSelector,
+    sides: &ModuleSides,
+) -> Option<usize> {
+    let find = |id: &str| {
+        mats.iter()
+            .position(|m| m.port_info.port_name == id || m.serial_number() == Some(id))
+    };
+
+    match selector {
+        ModuleSelector::Span => None,
+        ModuleSelector::Device(id) => find(id),
+        ModuleSelector::Left => match sides.left {
+            Some(ref serial) => find(serial),
+            None => unclaimed(mats, sides.right.as_deref()).next(),
+        },
+        ModuleSelector::Right => match sides.right {
+            Some(ref serial) => find(serial),
+            // Without any serial numbers, left is the first module and right is the second
+            None => match sides.left {
+                Some(ref serial) => unclaimed(mats, Some(serial)).next(),
+                None => unclaimed(mats, None).nth(1),
+            },
+        },
+    }
+}
+
+///
+/// Indices of the modules that aren't pinned to the other side
+///
+fn unclaimed<'a>(
+    mats: &'a [LedMatrix],
+    other: Option<&'a str>,
+) -> impl Iterator<Item = usize> + 'a {
+    mats.iter()
+        .enumerate()
+        .filter(move |(_, m)| other.is_none() || m.serial_number() != other)
+        .map(|(idx, _)| idx)
+}
+
+///
+/// A widget along with the module, position and size it is drawn at
+///
+struct PlacedWidget {
+    widget: Box<dyn UpdatableWidget>,
+    module: ModuleSelector,
+    x: usize,
+    y: usize,
+    size: Shape,
+    /// Whether we already warned that part of a span widget has no module to go on
+    warned: bool,
+}
+
+impl PlacedWidget {
+    ///
+    /// A widget placed by hand, drawn at its preferred size
+    ///
+    fn new(widget: Box<dyn UpdatableWidget>, module: ModuleSelector, x: usize, y: usize) -> Self {
+        PlacedWidget {
+            size: widget.preferred_size(),
+            widget,
+            module,
+            x,
+            y,
+            warned: false,
+        }
+    }
+
+    ///
+    /// Whether the widget fits on its module (or both, when spanning) at its
+    /// smallest size. Widgets that don't are cut off at the edge.
+    ///
+    fn fits(&self) -> bool {
+        let width = match self.module {
+            ModuleSelector::Span => 18,
+            _ => 9,
+        };
+        let size = self.widget.min_size();
+        let size = Shape {
+            x: size.x.min(self.size.x),
+            y: size.y.min(self.size.y),
+        };
+        self.x + size.x <= width && self.y + size.y <= 34
+    }
+}
+
+///
+/// Update every widget, then draw a single frame to each connected module.
+/// Widgets spanning both modules are drawn first, and split between the left and
+/// right modules. Widgets whose module isn't connected are still updated, but not drawn.
+/// The alert, if any, is drawn over the widgets.
+///
+fn render_frame(
+    modules: &mut Modules,
+    widgets: &mut [PlacedWidget],
+    sides: &ModuleSides,
+    alert: Option<&mut Alert>,
+) {
+    let mut frames = vec![[[0; 9]; 34]; modules.mats().len()];
+    let mut canvas: Canvas = [[0; 18]; 34];
+    // Whether everything drawn on each module is monochrome, None if nothing is
+    let mut monochrome = vec![None; frames.len()];
+    let mut mark = |idx: usize, mono: bool| {
+        monochrome[idx] = Some(monochrome[idx].unwrap_or(true) && mono);
+    };
+
+    let left_idx = select_module(modules.mats(), &ModuleSelector::Left, sides);
+    let right_idx = select_module(modules.mats(), &ModuleSelector::Right, sides);
+
+    let mut span_mono = None;
+    for placed in widgets.iter_mut() {
+        placed.widget.update();
+        if placed.module == ModuleSelector::Span {
+            // Once per disconnect, rather than every frame
+            let missing = match (left_idx, right_idx) {
+                (None, _) if placed.x < 9 => Some("left"),
+                (_, None) if placed.x + placed.size.x > 9 => Some("right"),
+                _ => None,
+            };
+            if let (Some(side), false) = (missing, placed.warned) {
+                println!(
+                    "Warning - Span widget at ({}, {}) is partly hidden, there is no {} module",
+                    placed.x, placed.y, side
+                );
+            }
+            placed.warned = missing.is_some();
+
+            canvas = matrix::emplace(
+                canvas,
+                placed.widget.as_ref(),
+                placed.x,
+                placed.y,
+                placed.size,
+            );
+            span_mono = Some(span_mono.unwrap_or(true) && placed.widget.monochrome());
+        }
+    }
+
+    let (left, right) = matrix::split(canvas);
+    for (idx, frame) in [(left_idx, left), (right_idx, right)] {
+        if let Some(idx) = idx {
+            frames[idx] = frame;
+            if let Some(mono) = span_mono {
+                mark(idx, mono);
+            }
+        }
+    }
+
+    for placed in widgets.iter() {
+        if let Some(idx) = select_module(modules.mats(), &placed.module, sides) {
+            frames[idx] = matrix::emplace(
+                frames[idx],
+                placed.widget.as_ref(),
+                placed.x,
+                placed.y,
+                placed.size,
+            );
+            mark(idx, placed.widget.monochrome());
+        }
+    }
+
+    let mut monochrome: Vec<bool> = monochrome.into_iter().map(|m| m == Some(true)).collect();
+
+    if let Some(alert) = alert {
+        alert.update();
+        // The alert has shades and can start at any time. Switching drawing paths while
+        // it plays would change the brightness, so every module stays on the one with
+        // shades for as long as an alert is set up.
+        monochrome.fill(false);
+        overlay_alert(&mut frames, alert, modules.mats(), sides);
+    }
+
+    modules.draw(frames, &monochrome);
+}
+
+///
+/// Draw the alert over the frames of the modules it is shown on
+///
+fn overlay_alert(frames: &mut [Matrix], alert: &Alert, mats: &[LedMatrix], sides: &ModuleSides) {
+    if !alert.is_active() {
+        return;
+    }
+
+    // Span and no module at all both cover every module
+    let target = match alert.module() {
+        None | Some(ModuleSelector::Span) => None,
+        Some(selector) => Some(select_module(mats, selector, sides)),
+    };
+    for (idx, frame) in frames.iter_mut().enumerate() {
+        if target.is_none() || target == Some(Some(idx)) {
+            *frame = alert.overlay(*frame);
+        }
+    }
+}
+
+///
+/// Send a diagnostic command to the selected modules (or all of them)
+///
+fn run_command(
+    command: &Command,
+    mats: &mut [LedMatrix],
+    module: Option<&ModuleSelector>,
+    sides: &ModuleSides,
+) {
+    let targets: Vec<usize> = match module {
+        Some(selector) => select_module(mats, selector, sides).into_iter().collect(),
+        None => (0..mats.len()).collect(),
+    };
+
+    if targets.is_empty() {
+        println!("No matching modules found.");
+        exit(1);
+    }
+
+    if let Command::Bootloader { confirm: false } = command {
+        println!("The module will stay blank until it is flashed or power cycled.");
+        println!("Run again with --confirm to reboot it into the bootloader.");
+        exit(1);
+    }
+
+    for idx in targets {
+        let mat = &mut mats[idx];
+        let res = match command {
+            Command::Pattern { pattern, percent } => mat.pattern(pattern.to_pattern(*percent)),
+            Command::Animate { enable } => mat.animate(*enable),
+            Command::Bootloader { confirm } => mat.enter_bootloader(*confirm),
+            Command::Panic => mat.panic(),
+        };
+
+        if let Err(e) = res {
+            println!("{} - {}", mat.port_info.port_name, e);
+        }
+    }
+}
+
+///
+/// Print every connected module, with its serial number and firmware version
+///
+fn list_modules(format: Format) {
+    let mut mats = LedMatrix::detect().unwrap_or_else(|e| {
+        println!("{}", e);
+        exit(1);
+    });
+    let infos: Vec<_> = mats.iter_mut().map(|m| m.info()).collect();
+
+    if format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(&infos).unwrap());
+        return;
+    }
+
+    if infos.is_empty() {
+        println!("No LED matrix modules found.");
+        return;
+    }
+
+    println!("Found LED matrix modules:");
+    for i in infos {
+        let serial = i.serial_number.unwrap_or("no serial".to_string());
+        match i.firmware_version {
+            Some(version) if !version.is_supported() => println!(
+                "{} ({}) - {} (too old, at least {} is required)",
+                i.port_name, serial, version, MIN_FW_VERSION
+            ),
+            Some(version) => println!("{} ({}) - {}", i.port_name, serial, version),
+            None => println!("{} ({}) - Unknown firmware version", i.port_name, serial),
+        }
+    }
+}
+
+///
+/// Print every widget that can be placed, along with its options
+///
+fn list_widgets(format: Format) {
+    let infos = widget::all_widgets();
+
+    if format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(&infos).unwrap());
+        return;
+    }
+
+    for info in infos {
+        println!(
+            "{} ({}) - {}x{}:\n {}",
+            info.title, info.name, info.shape.x, info.shape.y, info.description
+        );
+        for opt in info.options {
+            println!(
+                " - {} ({}, default {}): {}",
+                opt.name, opt.kind, opt.default, opt.description
+            );
+        }
+        println!();
+    }
 }
 
 enum Program {
     ListMod,
     ListWid,
+    Diagnostic(Command, Option<ModuleSelector>),
     Default,
 }
 
 fn main() {
-    // TODO possible options:
-    // each widget + Y placement + LED module (both as default) (for now, x maybe later)
-    // Overall brightness
-    // update rate
-
     let mut program = Program::Default;
 
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
 
     if cli.list_modules {
         program = Program::ListMod;
     } else if cli.list_widgets {
         program = Program::ListWid;
+    } else if let Some(command) = cli.command.take() {
+        program = Program::Diagnostic(command, cli.module.take().map(ModuleSelector::from));
     }
 
+    let format = cli.format;
     let settings = define_settings(cli);
 
-    if settings.rate > 5.5 {
-        println!("Framerate is currently limited to 5.5hz due to technical limitation. Will be fixed soon!");
-        exit(1);
-    }
-
     match program {
         Program::Default => {
-            let mut mats = LedMatrix::detect();
-            if mats.len() == 0 {
-                println!("No modules found, unable to continue.");
+            let mut modules =
+                Modules::new(Box::new(SystemPorts), settings.brightness, RESCAN_INTERVAL);
+            if let Err(e) = modules.scan() {
+                println!("{}", e);
                 exit(1);
             }
+            if modules.mats().is_empty() {
+                println!("No modules found, waiting for one to be connected.");
+            }
 
-            // No arguments provided? Start the default program
-            // if args().len() <= 1 {
-            let mut bat = BatteryWidget::new();
-            let mut cpu = AllCPUsWidget::new(false);
-            let mut clock = ClockWidget::new();
-
-            let blank = [[0; 9]; 34];
+            // Create every configured widget, along with the module it is drawn on
+            let mut widgets: Vec<PlacedWidget> = settings
+                .widgets
+                .iter()
+                .map(|cfg| PlacedWidget::new(cfg.kind.build(), cfg.module.clone(), cfg.x, cfg.y))
+                .collect();
 
-            if mats.len() == 2 {
-                mats[1].draw_matrix(blank);
+            // Then the ones placed by the layout engine
+            for layout in settings.layouts.iter() {
+                let placements = layout.build().unwrap_or_else(|e| {
+                    println!("{}", e);
+                    exit(1);
+                });
+                widgets.extend(placements.into_iter().map(|p| PlacedWidget {
+                    widget: p.widget,
+                    module: layout.module.clone(),
+                    x: p.x,
+                    y: p.y,
+                    size: p.size,
+                    warned: false,
+                }));
+            }
+            for placed in widgets.iter().filter(|placed| !placed.fits()) {
+                println!(
+                    "Warning - Widget at ({}, {}) doesn't fit on the module and will be cut off",
+                    placed.x, placed.y
+                );
             }
 
+            let mut alert = settings
+                .alert
+                .clone()
+                .map(|cfg| Alert::new(Box::new(SharedBatteries::system()), cfg));
+
             let mut saved_time = Instant::now();
 
             loop {
-                bat.update();
-                cpu.update();
-                clock.update();
-
-                let mut matrix = [[0; 9]; 34];
-                matrix = matrix::emplace(matrix, &mut bat, 0, 0);
-                matrix = matrix::emplace(matrix, &mut cpu, 0, 5);
-                matrix = matrix::emplace(matrix, &mut clock, 0, 23);
-                mats[0].draw_matrix(matrix);
+                modules.poll();
+                render_frame(&mut modules, &mut widgets, &settings.sides, alert.as_mut());
 
                 let period = Duration::from_secs_f64(1f64 / settings.rate);
                 let elapsed = Instant::now().duration_since(saved_time);
@@ -120,30 +552,272 @@ fn main() {
                 } else {
                     println!("Warning - Framerate is too fast!");
                 }
-                
+
                 saved_time = Instant::now();
-                
             }
-            // }
         }
-        Program::ListMod => {
-            LedMatrix::detect();
+        Program::ListMod => list_modules(format),
+        Program::Diagnostic(command, module) => {
+            let mut mats = LedMatrix::detect().unwrap_or_else(|e| {
+                println!("{}", e);
+                exit(1);
+            });
+            run_command(&command, &mut mats, module.as_ref(), &settings.sides);
         }
-        Program::ListWid => {
-            println!(
-                "Battery Indicator:\n \
-                A 9x4 widget in the shape of a battery, with an internal bar indicating remaining capacity.\n"
-            );
-            println!(
-                "CPU Usage Indicator:\n \
-                A 9x16 widget where each row of LEDs is a bar that represents the CPU usage of one core.\n"
-            );
-            println!(
-                "Clock Widget:\n \
-                A 9x11 widget that displays the system time in 24hr format.\n"
-            );
-        } // _ => {}
+        Program::ListWid => list_widgets(format),
     }
 
     exit(0);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        font::Font,
+        ledmatrix::DRAW_CMD,
+        mock::{MockBatteries, MockMatrix, MockPorts, MockWidget},
+        widget::{ClockMode, ClockSeconds, ClockWidget, TextWidget},
+    };
+
+    #[test]
+    fn render_frame_draws_each_module() {
+        let ports = MockPorts::new();
+        let left = ports.plug(MockPorts::ledmatrix_info("/dev/ttyACM0", "A"));
+        let right = ports.plug(MockPorts::ledmatrix_info("/dev/ttyACM1", "B"));
+        right.state().frame = [[1; 9]; 34];
+
+        let mut modules = Modules::new(Box::new(ports), None, Duration::ZERO);
+        modules.scan().unwrap();
+
+        let mut widgets = vec![PlacedWidget::new(
+            Box::new(ClockWidget::new(
+                false,
+                ClockSeconds::None,
+                ClockMode::Time,
+                None,
+            )),
+            ModuleSelector::Left,
+            0,
+            20,
+        )];
+
+        for _ in 0..3 {
+            render_frame(&mut modules, &mut widgets, &ModuleSides::default(), None);
+        }
+
+        let clock = widgets[0].widget.as_ref();
+        let expected = matrix::emplace([[0; 9]; 34], clock, 0, 20, clock.preferred_size());
+        assert_eq!(left.frame(), expected);
+        assert_eq!(right.frame(), [[0; 9]; 34]);
+    }
+
+    #[test]
+    fn render_frame_splits_spanning_canvas() {
+        let ports = MockPorts::new();
+        let left = ports.plug(MockPorts::ledmatrix_info("/dev/ttyACM0", "A"));
+        let right = ports.plug(MockPorts::ledmatrix_info("/dev/ttyACM1", "B"));
+
+        let mut modules = Modules::new(Box::new(ports), None, Duration::ZERO);
+        modules.scan().unwrap();
+
+        // Straddles the gap between the modules
+        let mut widgets = vec![PlacedWidget::new(
+            Box::new(ClockWidget::new(
+                false,
+                ClockSeconds::None,
+                ClockMode::Time,
+                None,
+            )),
+            ModuleSelector::Span,
+            5,
+            2,
+        )];
+        render_frame(&mut modules, &mut widgets, &ModuleSides::default(), None);
+
+        let clock = widgets[0].widget.as_ref();
+        let canvas = matrix::emplace([[0; 18]; 34], clock, 5, 2, clock.preferred_size());
+        let (l, r) = matrix::split(canvas);
+        assert_eq!(left.frame(), l);
+        assert_eq!(right.frame(), r);
+        assert_ne!(r, [[0; 9]; 34]);
+    }
+
+    #[test]
+    fn render_frame_span_without_right_module() {
+        let ports = MockPorts::new();
+        let left = ports.plug(MockPorts::ledmatrix_info("/dev/ttyACM0", "A"));
+        let mut modules = Modules::new(Box::new(ports.clone()), None, Duration::ZERO);
+        modules.scan().unwrap();
+
+        let span = |x: usize| {
+            let size = Shape { x: 4, y: 4 };
+            PlacedWidget::new(
+                Box::new(MockWidget::new(size, size)),
+                ModuleSelector::Span,
+                x,
+                0,
+            )
+        };
+        let mut widgets = vec![span(7), span(0)];
+
+        // Only the widget hanging over onto the missing module is warned about, once
+        for _ in 0..2 {
+            render_frame(&mut modules, &mut widgets, &ModuleSides::default(), None);
+            assert!(widgets[0].warned);
+            assert!(!widgets[1].warned);
+        }
+        assert_eq!(left.frame()[0][7..], [1, 1]);
+
+        // Plugging the right module in resets it, so losing it again is warned about
+        ports.plug(MockPorts::ledmatrix_info("/dev/ttyACM1", "B"));
+        modules.scan().unwrap();
+        render_frame(&mut modules, &mut widgets, &ModuleSides::default(), None);
+        assert!(!widgets[0].warned);
+    }
+
+    #[test]
+    fn render_frame_monochrome_modules() {
+        let ports = MockPorts::new();
+        let left = ports.plug(MockPorts::ledmatrix_info("/dev/ttyACM0", "A"));
+        let right = ports.plug(MockPorts::ledmatrix_info("/dev/ttyACM1", "B"));
+        let mut modules = Modules::new(Box::new(ports), None, Duration::ZERO);
+        modules.scan().unwrap();
+
+        let block = |monochrome: bool, module: ModuleSelector| {
+            let size = Shape { x: 3, y: 3 };
+            let mut widget = MockWidget::new(size, size);
+            widget.monochrome = monochrome;
+            PlacedWidget::new(Box::new(widget), module, 0, 0)
+        };
+        let draws = |mock: &MockMatrix| {
+            let mut state = mock.state();
+            let draws = state.commands.iter().filter(|&&c| c == DRAW_CMD).count();
+            state.commands.clear();
+            draws
+        };
+
+        // Only a monochrome widget on the left, the right one has shades too
+        let mut widgets = vec![
+            block(true, ModuleSelector::Left),
+            block(true, ModuleSelector::Right),
+            block(false, ModuleSelector::Right),
+        ];
+        render_frame(&mut modules, &mut widgets, &ModuleSides::default(), None);
+        assert_eq!(draws(&left), 1);
+        assert_eq!(draws(&right), 0);
+        assert_eq!(left.frame()[0][..4], [0xFF, 0xFF, 0xFF, 0]);
+        assert_eq!(right.frame()[0][..4], [1, 1, 1, 0]);
+
+        // A span widget counts for both modules
+        let mut widgets = vec![block(false, ModuleSelector::Span)];
+        widgets.push(block(true, ModuleSelector::Left));
+        render_frame(&mut modules, &mut widgets, &ModuleSides::default(), None);
+        assert_eq!(draws(&left), 0);
+        assert_eq!(draws(&right), 0);
+
+        // With an alert set up, even while it isn't playing
+        let reading = MockBatteries::reading(50.0, battery::State::Discharging);
+        let reads = vec![Some(vec![reading])];
+        let config = serde_json::from_str(r#"{ "threshold": 10 }"#).unwrap();
+        let mut alert = Alert::new(Box::new(MockBatteries::new(reads)), config);
+        let mut widgets = vec![block(true, ModuleSelector::Left)];
+        let sides = ModuleSides::default();
+        render_frame(&mut modules, &mut widgets, &sides, Some(&mut alert));
+        assert!(!alert.is_active());
+        assert_eq!(draws(&left), 0);
+        assert_eq!(left.frame()[0][..4], [1, 1, 1, 0]);
+    }
+
+    #[test]
+    fn render_frame_clips_widgets() {
+        let ports = MockPorts::new();
+        let left = ports.plug(MockPorts::ledmatrix_info("/dev/ttyACM0", "A"));
+        let mut modules = Modules::new(Box::new(ports), None, Duration::ZERO);
+        modules.scan().unwrap();
+
+        // 5x34, so it hangs off the right and the bottom of the module
+        let text = TextWidget::new("HELLO", Font::small(), true, None, 0);
+        let mut widgets = vec![PlacedWidget::new(
+            Box::new(text),
+            ModuleSelector::Left,
+            6,
+            5,
+        )];
+        assert!(!widgets[0].fits());
+        render_frame(&mut modules, &mut widgets, &ModuleSides::default(), None);
+
+        // The top left of the text is drawn, what doesn't fit is cut off
+        let frame = left.frame();
+        let text = Font::small().rotated().render("HELLO");
+        for (y, row) in frame.iter().enumerate() {
+            for (x, &led) in row.iter().enumerate() {
+                let expected = match (x.checked_sub(6), y.checked_sub(5)) {
+                    (Some(tx), Some(ty)) if ty < text.height => text.get(tx, ty),
+                    _ => 0,
+                };
+                assert_eq!(led, expected, "LED at ({}, {})", x, y);
+            }
+        }
+
+        widgets[0].x = 4;
+        widgets[0].y = 0;
+        assert!(widgets[0].fits());
+    }
+
+    #[test]
+    fn select_module_by_serial_number() {
+        let ports = MockPorts::new();
+        ports.plug(MockPorts::ledmatrix_info("/dev/ttyACM0", "B"));
+        ports.plug(MockPorts::ledmatrix_info("/dev/ttyACM1", "A"));
+        let mut modules = Modules::new(Box::new(ports), None, Duration::ZERO);
+        modules.scan().unwrap();
+        let mats = modules.mats();
+
+        let port = |idx: Option<usize>| idx.map(|i| mats[i].port_info.port_name.as_str());
+        let left = ModuleSelector::Left;
+        let right = ModuleSelector::Right;
+
+        // Unassigned, ordered by serial number
+        let sides = ModuleSides::default();
+        assert_eq!(
+            port(select_module(mats, &left, &sides)),
+            Some("/dev/ttyACM1")
+        );
+        assert_eq!(
+            port(select_module(mats, &right, &sides)),
+            Some("/dev/ttyACM0")
+        );
+
+        // Only one side pinned, the other module is the other side
+        let sides = ModuleSides {
+            left: Some("B".to_string()),
+            right: None,
+        };
+        assert_eq!(
+            port(select_module(mats, &left, &sides)),
+            Some("/dev/ttyACM0")
+        );
+        assert_eq!(
+            port(select_module(mats, &right, &sides)),
+            Some("/dev/ttyACM1")
+        );
+
+        // A pinned module that isn't connected is never replaced by the other one
+        let sides = ModuleSides {
+            left: Some("C".to_string()),
+            right: Some("A".to_string()),
+        };
+        assert_eq!(port(select_module(mats, &left, &sides)), None);
+        assert_eq!(
+            port(select_module(mats, &right, &sides)),
+            Some("/dev/ttyACM1")
+        );
+
+        let device = ModuleSelector::Device("B".to_string());
+        assert_eq!(
+            port(select_module(mats, &device, &sides)),
+            Some("/dev/ttyACM0")
+        );
+    }
+}
@@ -1,7 +1,10 @@
-use crate::widget::UpdatableWidget;
+use crate::widget::{Shape, UpdatableWidget};
 
 pub(crate) type Matrix = [[u8;9]; 34];
 
+/// Both modules side by side, treated as a single 18x34 canvas
+pub(crate) type Canvas = [[u8; 18]; 34];
+
 ///
 /// Encode a 9x34 array of booleans to a 39 byte (one bit per pixel) array
 /// [0][0] starts in top left corner
@@ -39,17 +42,158 @@ pub fn transpose(arr: Matrix) -> [[u8;34]; 9] {
 }
 
 ///
-/// Overlay a smaller matrix on a larger matrix with a given position
-/// 
-pub fn emplace(orig: Matrix, widget: &mut impl UpdatableWidget, x: usize, y: usize) -> Matrix {
-    // assert!(x as usize + widget.width < 9 && y as usize + widget.height < 34);
-    let mut out: [[u8; 9]; 34] = orig.clone();
+/// A rectangle of a frame that a widget draws into. Positions are relative to the top
+/// left of the view, and anything drawn outside of it is cut off, so a widget can't
+/// draw over the edge of the frame.
+///
+pub struct View<'a> {
+    leds: &'a mut [u8],
+    /// Width of the whole buffer
+    stride: usize,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl<'a> View<'a> {
+    ///
+    /// A view of a whole buffer of LEDs, stored row by row
+    ///
+    pub fn new(leds: &'a mut [u8], width: usize) -> View<'a> {
+        let height = leds.len().checked_div(width).unwrap_or(0);
+        View {
+            leds,
+            stride: width,
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }
+    }
+
+    ///
+    /// A view of a whole frame, a single module's Matrix or a spanning Canvas
+    ///
+    pub fn frame<const W: usize>(frame: &'a mut [[u8; W]; 34]) -> View<'a> {
+        View::new(frame.as_flattened_mut(), W)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    ///
+    /// A view of part of this view. The part outside of this view is cut off,
+    /// which can leave nothing.
+    ///
+    pub fn sub(&mut self, x: usize, y: usize, width: usize, height: usize) -> View<'_> {
+        View {
+            leds: self.leds,
+            stride: self.stride,
+            x: self.x + x.min(self.width),
+            y: self.y + y.min(self.height),
+            width: width.min(self.width.saturating_sub(x)),
+            height: height.min(self.height.saturating_sub(y)),
+        }
+    }
 
-    for i in 0..widget.get_shape().y {
-        for j in 0..widget.get_shape().x {
-            out[i + y][j + x] = widget.get_matrix()[j + (widget.get_shape().x * i)];
+    ///
+    /// Set a single LED. LEDs outside of the view are ignored.
+    ///
+    pub fn set(&mut self, x: usize, y: usize, led: u8) {
+        if x < self.width && y < self.height {
+            self.leds[(self.y + y) * self.stride + self.x + x] = led;
+        }
+    }
+
+    ///
+    /// Copy LEDs stored row by row, `width` per row, to the top left of the view
+    ///
+    pub fn draw(&mut self, leds: &[u8], width: usize) {
+        if width == 0 {
+            return;
+        }
+        for (idx, &led) in leds.iter().enumerate() {
+            self.set(idx % width, idx / width, led);
         }
     }
+}
+
+///
+/// Draw a widget onto a larger matrix (a single module's Matrix, or a spanning Canvas)
+/// with a given position and size. Parts of the widget that don't fit are cut off.
+///
+pub fn emplace<const W: usize>(
+    orig: [[u8; W]; 34],
+    widget: &dyn UpdatableWidget,
+    x: usize,
+    y: usize,
+    size: Shape,
+) -> [[u8; W]; 34] {
+    let mut out: [[u8; W]; 34] = orig;
+
+    let mut frame = View::frame(&mut out);
+    widget.render(&mut frame.sub(x, y, size.x, size.y));
 
     out
 }
+
+///
+/// Split a spanning canvas into the matrices for the left and right modules
+///
+pub fn split(canvas: Canvas) -> (Matrix, Matrix) {
+    let mut left: Matrix = [[0; 9]; 34];
+    let mut right: Matrix = [[0; 9]; 34];
+
+    for (i, row) in canvas.iter().enumerate() {
+        left[i].copy_from_slice(&row[..9]);
+        right[i].copy_from_slice(&row[9..]);
+    }
+
+    (left, right)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn view_clips() {
+        let mut frame: Matrix = [[0; 9]; 34];
+        let mut view = View::frame(&mut frame);
+        assert_eq!((view.width(), view.height()), (9, 34));
+
+        // Hangs off the right edge and the bottom
+        let mut sub = view.sub(7, 32, 4, 4);
+        assert_eq!((sub.width(), sub.height()), (2, 2));
+        sub.draw(&[1; 16], 4);
+        sub.set(1, 1, 2);
+        sub.set(2, 0, 3);
+
+        // Entirely outside of the frame
+        let mut outside = view.sub(12, 40, 3, 3);
+        assert_eq!((outside.width(), outside.height()), (0, 0));
+        outside.draw(&[5; 9], 3);
+
+        // Nested views are clipped to their parent
+        let mut sub = view.sub(0, 0, 3, 3);
+        let mut nested = sub.sub(2, 2, 5, 5);
+        assert_eq!((nested.width(), nested.height()), (1, 1));
+        nested.draw(&[4; 25], 5);
+
+        let lit: Vec<(usize, usize, u8)> = (0..34)
+            .flat_map(|y| (0..9).map(move |x| (x, y)))
+            .filter(|&(x, y)| frame[y][x] != 0)
+            .map(|(x, y)| (x, y, frame[y][x]))
+            .collect();
+        assert_eq!(
+            lit,
+            [(2, 2, 4), (7, 32, 1), (8, 32, 1), (7, 33, 1), (8, 33, 2)]
+        );
+    }
+}
@@ -0,0 +1,485 @@
+use std::{cell::RefCell, collections::VecDeque, io, rc::Rc};
+
+use crate::ledmatrix::{FirmwareVersion, LedMatrixError, LEDMATRIX_PID, LEDMATRIX_VID};
+use crate::{
+    ledmatrix::{
+        ANIMATE_CMD, BOOTLOADER_CMD, BRIGHTNESS_CMD, CHECKFW_CMD, CMD_START, COMMIT_COL, DRAW_CMD,
+        PANIC_CMD, PATTERN_CMD, RESPONSE_SIZE, SET_COL, SLEEP_CMD,
+    },
+    matrix::{Matrix, View},
+    power::{BatteryReading, BatterySource},
+    transport::{PortEnumerator, Transport},
+    widget::{Shape, UpdatableWidget},
+};
+use serialport::{SerialPortInfo, SerialPortType, UsbPortInfo};
+
+///
+/// Everything the simulated module remembers from the commands it received
+///
+pub struct DeviceState {
+    /// LEDs currently being displayed
+    pub frame: Matrix,
+    /// Columns written with SET_COL, waiting for COMMIT_COL
+    pub pending_cols: [[u8; 34]; 9],
+    pub brightness: u8,
+    pub sleeping: bool,
+    pub animating: bool,
+    pub pattern: Option<Vec<u8>>,
+    pub in_bootloader: bool,
+    pub panicked: bool,
+    /// Version reported to CHECKFW_CMD
+    pub version: FirmwareVersion,
+    /// Command byte of every command received, in order
+    pub commands: Vec<u8>,
+    /// Set to false to simulate the module being unplugged
+    pub connected: bool,
+    /// Bytes the device has sent back that have not been read yet
+    response: VecDeque<u8>,
+}
+
+///
+/// An in-memory LED matrix module. Decodes the 0x32 0xAC command stream into a
+/// framebuffer instead of talking to hardware. Clones share the same device, so a
+/// test can keep one and hand the other to LedMatrix::with_transport.
+///
+/// Every write is expected to hold exactly one command, which is how
+/// LedMatrix::sendcommand sends them.
+///
+#[derive(Clone)]
+pub struct MockMatrix {
+    state: Rc<RefCell<DeviceState>>,
+}
+
+impl MockMatrix {
+    pub fn new() -> MockMatrix {
+        MockMatrix {
+            state: Rc::new(RefCell::new(DeviceState {
+                frame: [[0; 9]; 34],
+                pending_cols: [[0; 34]; 9],
+                brightness: 255,
+                sleeping: false,
+                animating: false,
+                pattern: None,
+                in_bootloader: false,
+                panicked: false,
+                version: FirmwareVersion::new(0, 1, 9, false),
+                commands: vec![],
+                connected: true,
+                response: VecDeque::new(),
+            })),
+        }
+    }
+
+    ///
+    /// Access the device state, e.g. to inspect the frame after drawing
+    ///
+    pub fn state(&self) -> std::cell::RefMut<'_, DeviceState> {
+        self.state.borrow_mut()
+    }
+
+    ///
+    /// The LEDs currently displayed by the simulated module
+    ///
+    pub fn frame(&self) -> Matrix {
+        self.state.borrow().frame
+    }
+
+    fn respond(state: &mut DeviceState, bytes: &[u8]) {
+        let mut resp = [0; RESPONSE_SIZE];
+        resp[..bytes.len()].copy_from_slice(bytes);
+        state.response.extend(resp);
+    }
+
+    fn decode(&mut self, buf: &[u8]) -> io::Result<()> {
+        if buf.len() < 3 || buf[..2] != CMD_START {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Command does not start with 0x32 0xAC",
+            ));
+        }
+
+        let cmd = buf[2];
+        let params = &buf[3..];
+        let mut state = self.state.borrow_mut();
+        if !state.connected {
+            return Err(io::Error::from(io::ErrorKind::BrokenPipe));
+        }
+        state.commands.push(cmd);
+
+        match cmd {
+            BRIGHTNESS_CMD => match params.first() {
+                Some(val) => state.brightness = *val,
+                None => {
+                    let val = state.brightness;
+                    Self::respond(&mut state, &[val]);
+                }
+            },
+            PATTERN_CMD => state.pattern = Some(params.to_vec()),
+            BOOTLOADER_CMD => state.in_bootloader = true,
+            SLEEP_CMD => match params.first() {
+                Some(val) => state.sleeping = *val != 0,
+                None => {
+                    let val = state.sleeping as u8;
+                    Self::respond(&mut state, &[val]);
+                }
+            },
+            ANIMATE_CMD => match params.first() {
+                Some(val) => state.animating = *val != 0,
+                None => {
+                    let val = state.animating as u8;
+                    Self::respond(&mut state, &[val]);
+                }
+            },
+            PANIC_CMD => state.panicked = true,
+            DRAW_CMD => {
+                for y in 0..34 {
+                    for x in 0..9 {
+                        let bit = (y * 9) + x;
+                        let on = params
+                            .get(bit / 8)
+                            .is_some_and(|b| b & (1 << (bit % 8)) != 0);
+                        state.frame[y][x] = if on { 0xFF } else { 0x00 };
+                    }
+                }
+            }
+            SET_COL => {
+                if let Some((col, vals)) = params.split_first() {
+                    let col = *col as usize;
+                    if col < 9 {
+                        for (y, val) in vals.iter().take(34).enumerate() {
+                            state.pending_cols[col][y] = *val;
+                        }
+                    }
+                }
+            }
+            COMMIT_COL => {
+                for x in 0..9 {
+                    for y in 0..34 {
+                        state.frame[y][x] = state.pending_cols[x][y];
+                    }
+                }
+            }
+            CHECKFW_CMD => {
+                let v = state.version;
+                Self::respond(
+                    &mut state,
+                    &[
+                        v.major,
+                        (v.minor << 4) | (v.patch & 0x0F),
+                        v.pre_release as u8,
+                    ],
+                );
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+impl io::Write for MockMatrix {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.decode(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl io::Read for MockMatrix {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.state.borrow_mut();
+        let len = buf.len().min(state.response.len());
+        for (dst, src) in buf.iter_mut().zip(state.response.drain(..len)) {
+            *dst = src;
+        }
+        Ok(len)
+    }
+}
+
+impl Transport for MockMatrix {
+    fn bytes_to_read(&self) -> io::Result<u32> {
+        Ok(self.state.borrow().response.len() as u32)
+    }
+}
+
+///
+/// A fake set of serial ports. Ports can be plugged in and unplugged while a test
+/// is running, and opening a port hands out a transport to its MockMatrix.
+///
+#[derive(Clone, Default)]
+pub struct MockPorts {
+    ports: Rc<RefCell<Vec<(SerialPortInfo, MockMatrix)>>>,
+}
+
+impl MockPorts {
+    pub fn new() -> MockPorts {
+        MockPorts::default()
+    }
+
+    ///
+    /// The port info of an LED matrix module on the given port
+    ///
+    pub fn ledmatrix_info(port_name: &str, serial: &str) -> SerialPortInfo {
+        SerialPortInfo {
+            port_name: port_name.to_string(),
+            port_type: SerialPortType::UsbPort(UsbPortInfo {
+                vid: LEDMATRIX_VID,
+                pid: LEDMATRIX_PID,
+                serial_number: Some(serial.to_string()),
+                manufacturer: Some("Framework".to_string()),
+                product: Some("LED Matrix Input Module".to_string()),
+            }),
+        }
+    }
+
+    ///
+    /// Add a port to the system, returning the simulated device behind it
+    ///
+    pub fn plug(&self, info: SerialPortInfo) -> MockMatrix {
+        let mock = MockMatrix::new();
+        self.ports.borrow_mut().push((info, mock.clone()));
+        mock
+    }
+
+    ///
+    /// Remove a port from the system. Any open transport to it starts failing.
+    ///
+    pub fn unplug(&self, port_name: &str) {
+        self.ports.borrow_mut().retain(|(info, mock)| {
+            if info.port_name == port_name {
+                mock.state().connected = false;
+            }
+            info.port_name != port_name
+        });
+    }
+}
+
+impl PortEnumerator for MockPorts {
+    fn available_ports(&self) -> Result<Vec<SerialPortInfo>, LedMatrixError> {
+        Ok(self
+            .ports
+            .borrow()
+            .iter()
+            .map(|(info, _)| info.clone())
+            .collect())
+    }
+
+    fn open(&self, port: &SerialPortInfo) -> Result<Box<dyn Transport>, LedMatrixError> {
+        self.ports
+            .borrow()
+            .iter()
+            .find(|(info, _)| info.port_name == port.port_name)
+            .map(|(_, mock)| Box::new(mock.clone()) as Box<dyn Transport>)
+            .ok_or(LedMatrixError::DeviceGone)
+    }
+}
+
+///
+/// A fake battery source, handing out one set of readings per read.
+/// None simulates the battery driver failing, as does running out of readings.
+///
+pub struct MockBatteries {
+    reads: VecDeque<Option<Vec<BatteryReading>>>,
+}
+
+impl MockBatteries {
+    pub fn new(reads: Vec<Option<Vec<BatteryReading>>>) -> MockBatteries {
+        MockBatteries {
+            reads: reads.into(),
+        }
+    }
+
+    ///
+    /// A 100Wh battery at the given charge, drawing 10W
+    ///
+    pub fn reading(percent: f32, state: battery::State) -> BatteryReading {
+        BatteryReading {
+            energy: percent,
+            energy_full: 100.0,
+            energy_rate: 10.0,
+            state,
+            time_to_full: None,
+            time_to_empty: None,
+        }
+    }
+}
+
+impl BatterySource for MockBatteries {
+    fn read(&mut self) -> Result<Vec<BatteryReading>, battery::Error> {
+        let missing = io::Error::from(io::ErrorKind::NotFound);
+        self.reads.pop_front().flatten().ok_or(missing.into())
+    }
+}
+
+///
+/// A widget with whatever sizes a test needs, that lights up the whole view it's given
+///
+pub struct MockWidget {
+    preferred: Shape,
+    min: Shape,
+    /// What the widget reports from UpdatableWidget::monochrome
+    pub monochrome: bool,
+}
+
+impl MockWidget {
+    pub fn new(preferred: Shape, min: Shape) -> MockWidget {
+        MockWidget {
+            preferred,
+            min,
+            monochrome: false,
+        }
+    }
+}
+
+impl UpdatableWidget for MockWidget {
+    fn update(&mut self) {}
+
+    fn preferred_size(&self) -> Shape {
+        self.preferred
+    }
+
+    fn min_size(&self) -> Shape {
+        self.min
+    }
+
+    fn render(&self, view: &mut View) {
+        for y in 0..view.height() {
+            for x in 0..view.width() {
+                view.set(x, y, 1);
+            }
+        }
+    }
+
+    fn monochrome(&self) -> bool {
+        self.monochrome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledmatrix::{LedMatrix, Pattern};
+
+    fn connect(mock: &MockMatrix) -> LedMatrix {
+        LedMatrix::with_transport(
+            Box::new(mock.clone()),
+            SerialPortInfo {
+                port_name: "mock".to_string(),
+                port_type: SerialPortType::Unknown,
+            },
+        )
+    }
+
+    #[test]
+    fn draw_matrix_fills_framebuffer() {
+        let mock = MockMatrix::new();
+        let mut mat = connect(&mock);
+
+        let mut frame = [[0; 9]; 34];
+        frame[0][0] = 10;
+        frame[33][8] = 200;
+        frame[12][4] = 68;
+        mat.draw_matrix(frame).unwrap();
+
+        assert_eq!(mock.frame(), frame);
+    }
+
+    #[test]
+    fn draw_bool_matrix_fills_framebuffer() {
+        let mock = MockMatrix::new();
+        let mut mat = connect(&mock);
+
+        let mut frame = [[false; 9]; 34];
+        frame[1][3] = true;
+        frame[33][8] = true;
+        mat.draw_bool_matrix(frame).unwrap();
+
+        let drawn = mock.frame();
+        for y in 0..34 {
+            for x in 0..9 {
+                assert_eq!(drawn[y][x] != 0, frame[y][x]);
+            }
+        }
+    }
+
+    #[test]
+    fn brightness_sleep_and_version() {
+        let mock = MockMatrix::new();
+        let mut mat = connect(&mock);
+
+        mat.set_full_brightness(40).unwrap();
+        mat.sleep().unwrap();
+        assert_eq!(mock.state().brightness, 40);
+        assert!(mock.state().sleeping);
+
+        mat.wake().unwrap();
+        assert!(!mock.state().sleeping);
+
+        mock.state().version = FirmwareVersion::new(1, 2, 3, true);
+        let version = mat.get_fw_version().unwrap();
+        assert_eq!(version, FirmwareVersion::new(1, 2, 3, true));
+        assert_eq!(version.to_string(), "1.2.3-pre");
+        assert_eq!(
+            mock.state().commands,
+            vec![BRIGHTNESS_CMD, SLEEP_CMD, SLEEP_CMD, CHECKFW_CMD]
+        );
+    }
+
+    #[test]
+    fn firmware_version_ordering() {
+        let release = FirmwareVersion::new(0, 1, 9, false);
+        assert!(FirmwareVersion::new(0, 1, 9, true) < release);
+        assert!(FirmwareVersion::new(0, 1, 8, false) < FirmwareVersion::new(0, 1, 9, true));
+        assert!(FirmwareVersion::new(1, 0, 0, true) > release);
+        assert!(release.is_supported());
+        assert!(!FirmwareVersion::new(0, 1, 6, false).is_supported());
+    }
+
+    #[test]
+    fn read_back_state() {
+        let mock = MockMatrix::new();
+        let mut mat = connect(&mock);
+
+        // Changed by another program
+        mock.state().brightness = 77;
+        mock.state().sleeping = true;
+        mock.state().animating = true;
+
+        assert_eq!(mat.get_brightness().unwrap(), 77);
+        assert!(mat.is_sleeping().unwrap());
+        assert!(mat.is_animating().unwrap());
+
+        mat.wake().unwrap();
+        mat.animate(false).unwrap();
+        assert!(!mat.is_sleeping().unwrap());
+        assert!(!mat.is_animating().unwrap());
+    }
+
+    #[test]
+    fn firmware_commands() {
+        let mock = MockMatrix::new();
+        let mut mat = connect(&mock);
+
+        mat.pattern(Pattern::Percentage(150)).unwrap();
+        assert_eq!(mock.state().pattern, Some(vec![0x00, 100]));
+        mat.pattern(Pattern::ZigZag).unwrap();
+        assert_eq!(mock.state().pattern, Some(vec![0x04]));
+
+        mat.animate(true).unwrap();
+        assert!(mock.state().animating);
+
+        assert!(matches!(
+            mat.enter_bootloader(false),
+            Err(LedMatrixError::Unconfirmed)
+        ));
+        assert!(!mock.state().in_bootloader);
+        mat.enter_bootloader(true).unwrap();
+        assert!(mock.state().in_bootloader);
+
+        mat.panic().unwrap();
+        assert!(mock.state().panicked);
+    }
+}
@@ -0,0 +1,402 @@
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+
+use serialport::{SerialPortInfo, SerialPortType};
+
+use crate::{
+    ledmatrix::{LedMatrix, LedMatrixError},
+    matrix::Matrix,
+    transport::PortEnumerator,
+};
+
+/// Port name and USB serial number, so a different module on the same port is tried again
+type PortKey = (String, Option<String>);
+
+fn port_key(info: &SerialPortInfo) -> PortKey {
+    let serial = match info.port_type {
+        SerialPortType::UsbPort(ref usb) => usb.serial_number.clone(),
+        _ => None,
+    };
+    (info.port_name.clone(), serial)
+}
+
+///
+/// Keeps track of the LED matrix modules that are currently plugged in.
+/// Modules that stop responding are dropped, and the serial ports are
+/// periodically rescanned so modules that are (re)attached get picked up again.
+///
+/// Modules that another program put to sleep or started animating are left alone
+/// until they are woken up / stopped again.
+///
+pub struct Modules {
+    ports: Box<dyn PortEnumerator>,
+    mats: Vec<LedMatrix>,
+    /// Port names of the modules that are not being drawn to
+    paused: HashSet<String>,
+    /// Modules refused for their firmware, left alone until they are unplugged
+    refused: HashSet<PortKey>,
+    brightness: Option<u8>,
+    rescan_interval: Duration,
+    last_scan: Option<Instant>,
+}
+
+impl Modules {
+    ///
+    /// Create an empty set of modules. Nothing is connected until scan or poll is called.
+    /// If a brightness is given, it is applied to every module as it connects.
+    ///
+    pub fn new(
+        ports: Box<dyn PortEnumerator>,
+        brightness: Option<u8>,
+        rescan_interval: Duration,
+    ) -> Modules {
+        Modules {
+            ports,
+            mats: vec![],
+            paused: HashSet::new(),
+            refused: HashSet::new(),
+            brightness,
+            rescan_interval,
+            last_scan: None,
+        }
+    }
+
+    ///
+    /// The modules currently connected, sorted by USB serial number
+    ///
+    pub fn mats(&self) -> &[LedMatrix] {
+        &self.mats
+    }
+
+    ///
+    /// Look for LED matrix modules that are not connected yet, and connect to them
+    ///
+    pub fn scan(&mut self) -> Result<(), LedMatrixError> {
+        self.last_scan = Some(Instant::now());
+
+        let found = LedMatrix::find_ports(self.ports.as_ref())?;
+
+        // Forget refused modules once they are unplugged, they may come back updated
+        let keys: HashSet<PortKey> = found.iter().map(port_key).collect();
+        self.refused.retain(|key| keys.contains(key));
+
+        for info in found {
+            if self
+                .mats
+                .iter()
+                .any(|m| m.port_info.port_name == info.port_name)
+                || self.refused.contains(&port_key(&info))
+            {
+                continue;
+            }
+
+            match self.connect(info.clone()) {
+                Ok(mat) => self.mats.push(mat),
+                Err(e) => {
+                    println!("Unable to connect to {}: {}", info.port_name, e);
+                    if let LedMatrixError::UnsupportedFirmware(_) = e {
+                        self.refused.insert(port_key(&info));
+                    }
+                }
+            }
+        }
+
+        // Port names are handed out in the order modules enumerate, serial numbers
+        // stay the same, so sorting by them keeps the order stable across reboots
+        self.mats.sort_by(|a, b| {
+            (a.serial_number(), &a.port_info.port_name)
+                .cmp(&(b.serial_number(), &b.port_info.port_name))
+        });
+
+        Ok(())
+    }
+
+    fn connect(&self, info: SerialPortInfo) -> Result<LedMatrix, LedMatrixError> {
+        let mut mat = LedMatrix::open(self.ports.as_ref(), info)?;
+
+        // Modules that don't report a version are still used, in case they work anyway
+        match mat.get_fw_version() {
+            Ok(version) if !version.is_supported() => {
+                return Err(LedMatrixError::UnsupportedFirmware(version));
+            }
+            Ok(version) => println!("Connected to {} - {}", mat.port_info.port_name, version),
+            Err(e) => println!(
+                "Connected to {} - Unknown firmware version ({})",
+                mat.port_info.port_name, e
+            ),
+        }
+
+        if let Some(brightness) = self.brightness {
+            mat.set_full_brightness(brightness)?;
+        }
+
+        Ok(mat)
+    }
+
+    ///
+    /// Rescan for modules and sync with their state, if the rescan interval has
+    /// passed since the last scan
+    ///
+    pub fn poll(&mut self) {
+        let due = match self.last_scan {
+            Some(t) => t.elapsed() >= self.rescan_interval,
+            None => true,
+        };
+
+        if due {
+            if let Err(e) = self.scan() {
+                println!("Unable to scan for modules: {}", e);
+            }
+            self.sync();
+        }
+    }
+
+    ///
+    /// Read back whether each module is asleep or animating, and pause drawing to
+    /// the ones that are so we don't overwrite what another program set up
+    ///
+    pub fn sync(&mut self) {
+        let mut lost = vec![];
+
+        for (idx, mat) in self.mats.iter_mut().enumerate() {
+            let state = mat
+                .is_sleeping()
+                .and_then(|sleeping| Ok(sleeping || mat.is_animating()?));
+
+            let name = &mat.port_info.port_name;
+            match state {
+                Ok(true) => {
+                    if self.paused.insert(name.clone()) {
+                        println!("{} is asleep or animating, pausing", name);
+                    }
+                }
+                Ok(false) => {
+                    if self.paused.remove(name) {
+                        println!("{} is awake, resuming", name);
+                    }
+                }
+                Err(e) => {
+                    println!("Lost module {}: {}", name, e);
+                    lost.push(idx);
+                }
+            }
+        }
+
+        self.remove(lost);
+    }
+
+    fn remove(&mut self, idxs: Vec<usize>) {
+        for idx in idxs.into_iter().rev() {
+            let mat = self.mats.remove(idx);
+            self.paused.remove(&mat.port_info.port_name);
+        }
+    }
+
+    ///
+    /// Draw one frame per module, in the same order as mats(). Paused modules are
+    /// skipped. Modules marked as monochrome are sent which LEDs are lit, without their
+    /// brightness. Any module that fails to draw is considered lost, and is disconnected
+    /// until a rescan finds it again.
+    ///
+    pub fn draw(&mut self, frames: Vec<Matrix>, monochrome: &[bool]) {
+        let mut lost = vec![];
+
+        for (idx, (mat, frame)) in self.mats.iter_mut().zip(frames).enumerate() {
+            if self.paused.contains(&mat.port_info.port_name) {
+                continue;
+            }
+            let drawn = match monochrome.get(idx) {
+                Some(true) => mat.draw_bool_matrix(frame.map(|row| row.map(|led| led != 0))),
+                _ => mat.draw_matrix(frame),
+            };
+            if let Err(e) = drawn {
+                println!("Lost module {}: {}", mat.port_info.port_name, e);
+                lost.push(idx);
+            }
+        }
+
+        self.remove(lost);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ledmatrix::{FirmwareVersion, CHECKFW_CMD},
+        mock::{MockMatrix, MockPorts},
+    };
+
+    const BLANK: Matrix = [[0; 9]; 34];
+
+    fn modules(ports: &MockPorts) -> Modules {
+        Modules::new(Box::new(ports.clone()), Some(30), Duration::ZERO)
+    }
+
+    #[test]
+    fn scan_only_connects_to_ledmatrix_ports() {
+        let ports = MockPorts::new();
+        ports.plug(SerialPortInfo {
+            port_name: "/dev/ttyS0".to_string(),
+            port_type: SerialPortType::Unknown,
+        });
+        let mock = ports.plug(MockPorts::ledmatrix_info("/dev/ttyACM0", "A"));
+
+        let mut mods = modules(&ports);
+        mods.scan().unwrap();
+
+        assert_eq!(mods.mats().len(), 1);
+        assert_eq!(mods.mats()[0].port_info.port_name, "/dev/ttyACM0");
+        assert_eq!(mock.state().brightness, 30);
+    }
+
+    #[test]
+    fn lost_module_is_reattached() {
+        let ports = MockPorts::new();
+        ports.plug(MockPorts::ledmatrix_info("/dev/ttyACM0", "A"));
+
+        let mut mods = modules(&ports);
+        mods.poll();
+        assert_eq!(mods.mats().len(), 1);
+
+        // Suspend / unplug, the next draw notices the module is gone
+        ports.unplug("/dev/ttyACM0");
+        mods.draw(vec![BLANK], &[false]);
+        assert!(mods.mats().is_empty());
+
+        mods.poll();
+        assert!(mods.mats().is_empty());
+
+        // Plugged back in, and picked up by the next rescan
+        let mock = ports.plug(MockPorts::ledmatrix_info("/dev/ttyACM0", "A"));
+        mods.poll();
+        assert_eq!(mods.mats().len(), 1);
+
+        let mut frame = BLANK;
+        frame[3][3] = 120;
+        mods.draw(vec![frame], &[false]);
+        assert_eq!(mock.frame(), frame);
+    }
+
+    #[test]
+    fn second_module_appears_later() {
+        let ports = MockPorts::new();
+        ports.plug(MockPorts::ledmatrix_info("/dev/ttyACM0", "B"));
+
+        let mut mods = modules(&ports);
+        mods.poll();
+        assert_eq!(mods.mats().len(), 1);
+
+        ports.plug(MockPorts::ledmatrix_info("/dev/ttyACM1", "A"));
+        mods.poll();
+
+        // Sorted by serial number, not by port name
+        let names: Vec<&str> = mods
+            .mats()
+            .iter()
+            .map(|m| m.port_info.port_name.as_str())
+            .collect();
+        assert_eq!(names, vec!["/dev/ttyACM1", "/dev/ttyACM0"]);
+    }
+
+    #[test]
+    fn sleeping_module_is_not_drawn() {
+        let ports = MockPorts::new();
+        let mock = ports.plug(MockPorts::ledmatrix_info("/dev/ttyACM0", "A"));
+
+        let mut mods = modules(&ports);
+        mods.poll();
+
+        // Another program puts the module to sleep
+        mock.state().sleeping = true;
+        mods.poll();
+
+        let mut frame = BLANK;
+        frame[0][0] = 120;
+        mods.draw(vec![frame], &[false]);
+        assert_eq!(mock.frame(), BLANK);
+
+        mock.state().sleeping = false;
+        mods.poll();
+        mods.draw(vec![frame], &[false]);
+        assert_eq!(mock.frame(), frame);
+    }
+
+    #[test]
+    fn old_firmware_is_refused() {
+        let ports = MockPorts::new();
+        let old = ports.plug(MockPorts::ledmatrix_info("/dev/ttyACM0", "A"));
+        old.state().version = FirmwareVersion::new(0, 1, 2, false);
+        ports.plug(MockPorts::ledmatrix_info("/dev/ttyACM1", "B"));
+
+        let mut mods = modules(&ports);
+        mods.scan().unwrap();
+
+        assert_eq!(mods.mats().len(), 1);
+        assert_eq!(mods.mats()[0].port_info.port_name, "/dev/ttyACM1");
+    }
+
+    #[test]
+    fn refused_module_is_not_retried() {
+        let ports = MockPorts::new();
+        let old = ports.plug(MockPorts::ledmatrix_info("/dev/ttyACM0", "A"));
+        old.state().version = FirmwareVersion::new(0, 1, 2, false);
+
+        let version_checks = |mock: &MockMatrix| {
+            mock.state()
+                .commands
+                .iter()
+                .filter(|&&c| c == CHECKFW_CMD)
+                .count()
+        };
+
+        let mut mods = modules(&ports);
+        for _ in 0..3 {
+            mods.poll();
+        }
+        assert!(mods.mats().is_empty());
+        assert_eq!(version_checks(&old), 1);
+
+        // A different module on the same port is tried
+        ports.unplug("/dev/ttyACM0");
+        let new = ports.plug(MockPorts::ledmatrix_info("/dev/ttyACM0", "B"));
+        mods.poll();
+        assert_eq!(mods.mats().len(), 1);
+        assert_eq!(version_checks(&new), 1);
+    }
+
+    #[test]
+    fn refused_module_is_retried_after_unplug() {
+        let ports = MockPorts::new();
+        let old = ports.plug(MockPorts::ledmatrix_info("/dev/ttyACM0", "A"));
+        old.state().version = FirmwareVersion::new(0, 1, 2, false);
+
+        let mut mods = modules(&ports);
+        mods.poll();
+        assert!(mods.mats().is_empty());
+
+        ports.unplug("/dev/ttyACM0");
+        mods.poll();
+
+        // Updated and plugged back in
+        ports.plug(MockPorts::ledmatrix_info("/dev/ttyACM0", "A"));
+        mods.poll();
+        assert_eq!(mods.mats().len(), 1);
+    }
+
+    #[test]
+    fn poll_waits_for_rescan_interval() {
+        let ports = MockPorts::new();
+        let mut mods = Modules::new(Box::new(ports.clone()), None, Duration::from_secs(3600));
+        mods.poll();
+
+        ports.plug(MockPorts::ledmatrix_info("/dev/ttyACM0", "A"));
+        mods.poll();
+        assert!(mods.mats().is_empty());
+
+        mods.scan().unwrap();
+        assert_eq!(mods.mats().len(), 1);
+    }
+}
@@ -0,0 +1,288 @@
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use battery::units::{energy::watt_hour, power::watt, time::second};
+
+///
+/// A snapshot of a single battery, or of several batteries combined
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BatteryReading {
+    /// Energy currently stored, in Wh
+    pub energy: f32,
+    /// Energy stored when fully charged, in Wh
+    pub energy_full: f32,
+    /// Power flowing in or out of the battery, in W
+    pub energy_rate: f32,
+    pub state: battery::State,
+    /// Seconds until fully charged, when charging
+    pub time_to_full: Option<f32>,
+    /// Seconds until empty, when discharging
+    pub time_to_empty: Option<f32>,
+}
+
+impl BatteryReading {
+    fn from_battery(bat: &battery::Battery) -> BatteryReading {
+        BatteryReading {
+            energy: bat.energy().get::<watt_hour>(),
+            energy_full: bat.energy_full().get::<watt_hour>(),
+            energy_rate: bat.energy_rate().get::<watt>(),
+            state: bat.state(),
+            time_to_full: bat.time_to_full().map(|t| t.get::<second>()),
+            time_to_empty: bat.time_to_empty().map(|t| t.get::<second>()),
+        }
+    }
+
+    ///
+    /// Charge level, in percent
+    ///
+    pub fn percent(&self) -> f32 {
+        match self.energy_full {
+            full if full > 0.0 => (self.energy / full * 100.0).clamp(0.0, 100.0),
+            _ => 0.0,
+        }
+    }
+
+    ///
+    /// Combine several batteries into one as large as all of them together.
+    /// Charging wins over discharging, and the combination is only full or empty
+    /// when every battery is. Returns None if there are no batteries.
+    ///
+    pub fn combine(readings: &[BatteryReading]) -> Option<BatteryReading> {
+        if readings.len() <= 1 {
+            return readings.first().copied();
+        }
+
+        let energy: f32 = readings.iter().map(|r| r.energy).sum();
+        let energy_full: f32 = readings.iter().map(|r| r.energy_full).sum();
+        let energy_rate: f32 = readings.iter().map(|r| r.energy_rate).sum();
+
+        let any = |state| readings.iter().any(|r| r.state == state);
+        let all = |state| readings.iter().all(|r| r.state == state);
+        let state = if any(battery::State::Charging) {
+            battery::State::Charging
+        } else if any(battery::State::Discharging) {
+            battery::State::Discharging
+        } else if all(battery::State::Full) {
+            battery::State::Full
+        } else if all(battery::State::Empty) {
+            battery::State::Empty
+        } else {
+            battery::State::Unknown
+        };
+
+        // The batteries drain / charge together, so the times come from the totals
+        let seconds = |wh: f32| (energy_rate > 0.0).then(|| wh / energy_rate * 3600.0);
+        let (time_to_full, time_to_empty) = match state {
+            battery::State::Charging => (seconds(energy_full - energy), None),
+            battery::State::Discharging => (None, seconds(energy)),
+            _ => (None, None),
+        };
+
+        Some(BatteryReading {
+            energy,
+            energy_full,
+            energy_rate,
+            state,
+            time_to_full,
+            time_to_empty,
+        })
+    }
+}
+
+///
+/// Where the battery widget gets its readings from. Implemented by SystemBatteries
+/// for the batteries of this machine, and can be replaced to test the widget without them.
+///
+pub trait BatterySource {
+    ///
+    /// Read every battery. An empty list means there are no batteries (e.g. a desktop).
+    ///
+    fn read(&mut self) -> Result<Vec<BatteryReading>, battery::Error>;
+}
+
+///
+/// The batteries of the machine we are running on. The manager and batteries are
+/// kept between reads, and looked up again after an error or while none are found.
+///
+#[derive(Default)]
+pub struct SystemBatteries {
+    manager: Option<battery::Manager>,
+    batteries: Vec<battery::Battery>,
+}
+
+impl SystemBatteries {
+    pub fn new() -> SystemBatteries {
+        SystemBatteries::default()
+    }
+
+    fn refresh(&mut self) -> Result<(), battery::Error> {
+        let manager = match &self.manager {
+            Some(manager) => manager,
+            None => self.manager.insert(battery::Manager::new()?),
+        };
+
+        if self.batteries.is_empty() {
+            self.batteries = manager.batteries()?.collect::<Result<_, _>>()?;
+            return Ok(());
+        }
+
+        for bat in self.batteries.iter_mut() {
+            manager.refresh(bat)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl BatterySource for SystemBatteries {
+    fn read(&mut self) -> Result<Vec<BatteryReading>, battery::Error> {
+        if let Err(e) = self.refresh() {
+            // Start over on the next read, in case the batteries changed
+            self.batteries.clear();
+            return Err(e);
+        }
+
+        Ok(self
+            .batteries
+            .iter()
+            .map(BatteryReading::from_battery)
+            .collect())
+    }
+}
+
+///
+/// One battery source shared by every battery widget and the alert, so the batteries
+/// are only polled once per frame. Clones share the same source, and reads shortly
+/// after a successful read get the same readings back.
+///
+#[derive(Clone)]
+pub struct SharedBatteries {
+    inner: Rc<RefCell<Shared>>,
+}
+
+struct Shared {
+    source: Box<dyn BatterySource>,
+    max_age: Duration,
+    last: Option<(Instant, Vec<BatteryReading>)>,
+}
+
+impl SharedBatteries {
+    /// How long readings are reused for, shorter than a frame at the fastest rate
+    const MAX_AGE: Duration = Duration::from_millis(100);
+
+    pub fn new(source: Box<dyn BatterySource>, max_age: Duration) -> SharedBatteries {
+        SharedBatteries {
+            inner: Rc::new(RefCell::new(Shared {
+                source,
+                max_age,
+                last: None,
+            })),
+        }
+    }
+
+    ///
+    /// The batteries of the machine we are running on
+    ///
+    pub fn system() -> SharedBatteries {
+        thread_local! {
+            static SYSTEM: SharedBatteries =
+                SharedBatteries::new(Box::new(SystemBatteries::new()), SharedBatteries::MAX_AGE);
+        }
+        SYSTEM.with(SharedBatteries::clone)
+    }
+}
+
+impl BatterySource for SharedBatteries {
+    fn read(&mut self) -> Result<Vec<BatteryReading>, battery::Error> {
+        let mut shared = self.inner.borrow_mut();
+        if let Some((at, readings)) = &shared.last {
+            if at.elapsed() < shared.max_age {
+                return Ok(readings.clone());
+            }
+        }
+
+        // Errors aren't kept, so the next reader tries again
+        let readings = shared.source.read()?;
+        shared.last = Some((Instant::now(), readings.clone()));
+        Ok(readings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockBatteries;
+
+    fn reading(energy: f32, energy_full: f32, rate: f32, state: battery::State) -> BatteryReading {
+        BatteryReading {
+            energy,
+            energy_full,
+            energy_rate: rate,
+            state,
+            time_to_full: None,
+            time_to_empty: None,
+        }
+    }
+
+    #[test]
+    fn combine_batteries() {
+        assert_eq!(BatteryReading::combine(&[]), None);
+
+        let single = reading(10.0, 40.0, 5.0, battery::State::Discharging);
+        assert_eq!(BatteryReading::combine(&[single]), Some(single));
+
+        let both = BatteryReading::combine(&[
+            single,
+            reading(30.0, 40.0, 5.0, battery::State::Discharging),
+        ])
+        .unwrap();
+        assert_eq!(both.percent(), 50.0);
+        assert_eq!(both.state, battery::State::Discharging);
+        // 40Wh left at 10W
+        assert_eq!(both.time_to_empty, Some(4.0 * 3600.0));
+        assert_eq!(both.time_to_full, None);
+    }
+
+    #[test]
+    fn combined_state() {
+        let full = reading(40.0, 40.0, 0.0, battery::State::Full);
+        let charging = reading(20.0, 40.0, 10.0, battery::State::Charging);
+
+        let both = BatteryReading::combine(&[full, charging]).unwrap();
+        assert_eq!(both.state, battery::State::Charging);
+        assert_eq!(both.time_to_full, Some(2.0 * 3600.0));
+
+        let both = BatteryReading::combine(&[full, full]).unwrap();
+        assert_eq!(both.state, battery::State::Full);
+        assert_eq!(both.time_to_full, None);
+    }
+
+    #[test]
+    fn shared_batteries() {
+        let before = MockBatteries::reading(50.0, battery::State::Discharging);
+        let after = MockBatteries::reading(40.0, battery::State::Discharging);
+        let reads = || vec![Some(vec![before]), None, Some(vec![after])];
+
+        // Read once, by whichever reader comes first
+        let mut widget = SharedBatteries::new(
+            Box::new(MockBatteries::new(reads())),
+            Duration::from_secs(3600),
+        );
+        let mut alert = widget.clone();
+        assert_eq!(widget.read().unwrap(), [before]);
+        assert_eq!(alert.read().unwrap(), [before]);
+        assert_eq!(widget.read().unwrap(), [before]);
+
+        // Once the readings are too old, they are read again
+        let mut widget =
+            SharedBatteries::new(Box::new(MockBatteries::new(reads())), Duration::ZERO);
+        let mut alert = widget.clone();
+        assert_eq!(widget.read().unwrap(), [before]);
+        assert!(alert.read().is_err());
+        assert_eq!(widget.read().unwrap(), [after]);
+    }
+}
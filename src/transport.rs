@@ -0,0 +1,51 @@
+use std::io;
+
+use serialport::SerialPortInfo;
+
+use crate::ledmatrix::LedMatrixError;
+
+///
+/// A byte stream connected to an LED matrix module. LedMatrix only talks to
+/// the module through this trait, so it can be backed by a real serial port or
+/// by a simulated device.
+///
+pub trait Transport: io::Read + io::Write {
+    ///
+    /// Number of bytes received from the module that are waiting to be read
+    ///
+    fn bytes_to_read(&self) -> io::Result<u32>;
+}
+
+impl Transport for Box<dyn serialport::SerialPort> {
+    fn bytes_to_read(&self) -> io::Result<u32> {
+        serialport::SerialPort::bytes_to_read(self.as_ref()).map_err(io::Error::from)
+    }
+}
+
+///
+/// Lists the serial ports on the system and opens them. Implemented by SystemPorts
+/// for real hardware, and can be replaced to test module detection without it.
+///
+pub trait PortEnumerator {
+    fn available_ports(&self) -> Result<Vec<SerialPortInfo>, LedMatrixError>;
+    fn open(&self, port: &SerialPortInfo) -> Result<Box<dyn Transport>, LedMatrixError>;
+}
+
+///
+/// The serial ports of the machine we are running on
+///
+pub struct SystemPorts;
+
+impl PortEnumerator for SystemPorts {
+    fn available_ports(&self) -> Result<Vec<SerialPortInfo>, LedMatrixError> {
+        serialport::available_ports().map_err(LedMatrixError::Enumerate)
+    }
+
+    fn open(&self, port: &SerialPortInfo) -> Result<Box<dyn Transport>, LedMatrixError> {
+        let port0 = serialport::new(port.port_name.to_string(), 115_200)
+            .open()
+            .map_err(LedMatrixError::PortOpen)?;
+
+        Ok(Box::new(port0))
+    }
+}
@@ -1,10 +1,31 @@
-use chrono::{Local, Timelike};
+use std::{
+    collections::VecDeque,
+    fs,
+    io::BufReader,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
-const ON_FULL: u8 = 120;
-const ON_DIM: u8 = 68;
-const OFF: u8 = 0;
+use chrono::{Datelike, Local, Timelike};
+use image::{
+    codecs::gif::GifDecoder, imageops::FilterType, AnimationDecoder, DynamicImage, ImageFormat,
+    ImageReader,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 
-#[derive(Clone)]
+use crate::{
+    font::{Bitmap, Font},
+    matrix::View,
+    power::{BatteryReading, BatterySource},
+};
+
+/// LED brightnesses used by every widget, font and animation
+pub(crate) const ON_FULL: u8 = 120;
+pub(crate) const ON_DIM: u8 = 68;
+pub(crate) const OFF: u8 = 0;
+
+#[derive(Clone, Copy, Serialize, PartialEq, Debug)]
 pub struct Shape {
     pub x: usize,
     pub y: usize,
@@ -12,118 +33,276 @@ pub struct Shape {
 
 /// A standard set of instructions for widgets that can be updated from the system
 pub trait UpdatableWidget {
+    /// Read new values from the system, once per frame before the widget is drawn
     fn update(&mut self);
-    fn get_matrix(&self) -> &Vec<u8>;
-    fn get_shape(&self) -> &Shape;
+    /// Size the widget is drawn at when there is room for it
+    fn preferred_size(&self) -> Shape;
+    /// Smallest size the widget can still be drawn at, the preferred size unless
+    /// the widget can shrink
+    fn min_size(&self) -> Shape {
+        self.preferred_size()
+    }
+    /// Draw into a view of the frame, which is the preferred size unless there isn't
+    /// room for it. Anything drawn outside of the view is cut off.
+    fn render(&self, view: &mut View);
+    /// Whether the widget only draws fully lit or unlit LEDs. Modules showing nothing
+    /// else are drawn with draw_bool_matrix, which sends far less data.
+    fn monochrome(&self) -> bool {
+        false
+    }
 }
 
-// ================ Frames ================
-/// Battery frame with empty interior (9x4 shape)
-const BAT_FRAME: &'static [u8] = [
-    ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF, ON_FULL, OFF, OFF,
-    OFF, OFF, OFF, OFF, ON_FULL, ON_FULL, ON_FULL, OFF, OFF, OFF, OFF, OFF, OFF, ON_FULL, ON_FULL,
-    ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF,
-]
-.as_slice();
-
-const DIGIT_0: &'static [u8] = [
-    OFF, ON_FULL, OFF, ON_FULL, OFF, ON_FULL, ON_FULL, OFF, ON_FULL, ON_FULL, OFF, ON_FULL, OFF,
-    ON_FULL, OFF,
-]
-.as_slice();
-
-const DIGIT_1: &'static [u8] = [
-    OFF, OFF, ON_FULL, OFF, ON_DIM, ON_FULL, OFF, OFF, ON_FULL, OFF, OFF, ON_FULL, OFF, OFF,
-    ON_FULL,
-]
-.as_slice();
-
-const DIGIT_2: &'static [u8] = [
-    ON_FULL, ON_FULL, ON_FULL, OFF, OFF, ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF, OFF,
-    ON_FULL, ON_FULL, ON_FULL,
-]
-.as_slice();
+/// A widget specific option that can be set in the config file
+#[derive(Serialize)]
+pub struct WidgetOption {
+    pub name: &'static str,
+    /// JSON type of the option
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub default: serde_json::Value,
+    pub description: &'static str,
+}
 
-const DIGIT_3: &'static [u8] = [
-    ON_FULL, ON_FULL, ON_FULL, OFF, OFF, ON_FULL, ON_FULL, ON_FULL, OFF, OFF, OFF, ON_FULL,
-    ON_FULL, ON_FULL, ON_FULL,
-]
-.as_slice();
+/// Describes a type of widget, for listing the widgets available for placement
+#[derive(Serialize)]
+pub struct WidgetInfo {
+    /// Name used as the "type" of the widget in the config file
+    pub name: &'static str,
+    pub title: &'static str,
+    pub description: &'static str,
+    /// Shape of the widget with its default options
+    pub shape: Shape,
+    pub options: Vec<WidgetOption>,
+}
 
-const DIGIT_4: &'static [u8] = [
-    ON_FULL, OFF, ON_FULL, ON_FULL, OFF, ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF, OFF, ON_FULL,
-    OFF, OFF, ON_FULL,
-]
-.as_slice();
+/// Every widget available for placement
+pub fn all_widgets() -> Vec<WidgetInfo> {
+    vec![
+        BatteryWidget::info(),
+        AllCPUsWidget::info(),
+        ClockWidget::info(),
+        MemoryWidget::info(),
+        DiskWidget::info(),
+        NetworkWidget::info(),
+        CpuHistoryWidget::info(),
+        TemperatureWidget::info(),
+        TextWidget::info(),
+        ImageWidget::info(),
+    ]
+}
 
-const DIGIT_5: &'static [u8] = [
-    ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF, OFF, ON_FULL, ON_FULL, ON_FULL, OFF, OFF, ON_FULL,
-    ON_FULL, ON_FULL, ON_FULL,
-]
-.as_slice();
+///
+/// Fill a bar inside a widget's matrix, proportional to `frac` (0.0 - 1.0).
+/// Horizontal bars fill from left to right, vertical bars from the bottom up.
+///
+fn fill_bar(
+    matrix: &mut [u8],
+    stride: usize,
+    (x, y, w, h): (usize, usize, usize, usize),
+    frac: f32,
+    vertical: bool,
+    brightness: u8,
+) {
+    let len = if vertical { h } else { w };
+    let lit = (frac.clamp(0.0, 1.0) * len as f32).round() as usize;
 
-const DIGIT_6: &'static [u8] = [
-    OFF, ON_FULL, ON_DIM, ON_FULL, OFF, OFF, ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF, ON_FULL,
-    ON_FULL, ON_FULL, ON_FULL,
-]
-.as_slice();
+    for dy in 0..h {
+        for dx in 0..w {
+            let on = if vertical { h - dy <= lit } else { dx < lit };
+            if on {
+                matrix[(y + dy) * stride + x + dx] = brightness;
+            }
+        }
+    }
+}
 
-const DIGIT_7: &'static [u8] = [
-    ON_FULL, ON_FULL, ON_FULL, ON_DIM, OFF, ON_FULL, OFF, OFF, ON_FULL, OFF, ON_FULL, OFF, OFF,
-    ON_FULL, OFF,
-]
-.as_slice();
+///
+/// A percentage in the same 9x5 block as ClockWidget::render_number. 100% doesn't
+/// fit in two digits, so it is shown as "FL" (full) instead.
+///
+fn render_percent(pct: u32) -> Vec<u8> {
+    if pct < 100 {
+        return ClockWidget::render_number(pct);
+    }
 
-const DIGIT_8: &'static [u8] = [
-    ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF, ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF,
-    ON_FULL, ON_FULL, ON_FULL, ON_FULL,
-]
-.as_slice();
+    let mut block = Bitmap::new(9, 5);
+    block.blit(&Font::small().render("FL"), 1, 0);
+    block.pixels
+}
 
-const DIGIT_9: &'static [u8] = [
-    ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF, ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF, OFF, ON_FULL,
-    ON_DIM, ON_FULL, OFF,
+// ================ Frames ================
+/// Battery frame with empty interior (9x4 shape)
+const BAT_FRAME: &'static [u8] = [
+    ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF, ON_FULL, OFF, OFF,
+    OFF, OFF, OFF, OFF, ON_FULL, ON_FULL, ON_FULL, OFF, OFF, OFF, OFF, OFF, OFF, ON_FULL, ON_FULL,
+    ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, ON_FULL, OFF,
 ]
 .as_slice();
 
 // ================ Widgets ================
 /// -------- Battery Widget --------
+/// What the battery widget displays
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum BatteryMode {
+    /// Battery shaped bar of the remaining capacity
+    #[default]
+    Bar,
+    /// Remaining capacity in percent, as two digits
+    Percent,
+    /// Hours above minutes until empty, or until full (dimmed) while charging
+    Time,
+    /// Power draw in watts, as two digits, dimmed while charging
+    Power,
+}
+
 /// Create a widget that displays the battery remaining in the laptop
 pub struct BatteryWidget {
+    source: Box<dyn BatterySource>,
+    /// Which battery to show, all of them combined if None
+    index: Option<usize>,
+    mode: BatteryMode,
+    reading: Option<BatteryReading>,
     matrix: Vec<u8>,
     shape: Shape,
     chrg_ind: bool
 }
 
 impl BatteryWidget {
-    pub fn new() -> BatteryWidget {
+    pub fn info() -> WidgetInfo {
+        WidgetInfo {
+            name: "battery",
+            title: "Battery Indicator",
+            description:
+                "Shaped like a battery, with an internal bar indicating remaining capacity.",
+            shape: Self::mode_shape(BatteryMode::Bar),
+            options: vec![
+                WidgetOption {
+                    name: "index",
+                    kind: "number",
+                    default: json!(null),
+                    description: "Which battery to show, all batteries combined if not set",
+                },
+                WidgetOption {
+                    name: "mode",
+                    kind: "string",
+                    default: json!("bar"),
+                    description: "One of bar, percent, time (9x11) or power (watts)",
+                },
+            ],
+        }
+    }
+
+    fn mode_shape(mode: BatteryMode) -> Shape {
+        match mode {
+            BatteryMode::Bar => Shape { x: 9, y: 4 },
+            BatteryMode::Percent | BatteryMode::Power => Shape { x: 9, y: 5 },
+            BatteryMode::Time => Shape { x: 9, y: 11 },
+        }
+    }
+
+    pub fn new(
+        source: Box<dyn BatterySource>,
+        index: Option<usize>,
+        mode: BatteryMode,
+    ) -> BatteryWidget {
         println!("Initializing BatteryWidget");
         BatteryWidget { 
+            source,
+            index,
+            mode,
+            reading: None,
             matrix: vec![], 
             chrg_ind: false,
-            shape: Shape{x: 9, y:4}
+            shape: Self::mode_shape(mode),
         }
     }
-}
 
-impl UpdatableWidget for BatteryWidget {
-    fn update(&mut self) {
-        // Update the battery percentage
-        let battery_dev = battery::Manager::new()
-            .unwrap()
-            .batteries()
-            .unwrap()
-            .enumerate()
-            .next()
-            .unwrap()
-            .1
-            .unwrap();
+    ///
+    /// Read the selected battery. If the batteries can't be read, the last reading
+    /// is kept so a hiccup in the battery driver doesn't blank the widget.
+    ///
+    fn read(&mut self) {
+        match self.source.read() {
+            Ok(readings) => {
+                self.reading = match self.index {
+                    Some(idx) => readings.get(idx).copied(),
+                    None => BatteryReading::combine(&readings),
+                };
+            }
+            Err(e) => println!("BatteryWidget: Unable to read batteries: {}", e),
+        }
+    }
+
+    ///
+    /// Dim battery outline crossed out by a bright line, for when there's no battery
+    ///
+    fn render_missing(&mut self) {
+        self.matrix = BAT_FRAME
+            .iter()
+            .map(|&led| if led == OFF { OFF } else { ON_DIM })
+            .collect();
+
+        for x in 4..7 {
+            self.matrix[self.shape.x + x] = ON_FULL;
+        }
+        for x in 1..4 {
+            self.matrix[(self.shape.x * 2) + x] = ON_FULL;
+        }
+
+        // Other modes are larger than the battery outline
+        self.matrix.resize(self.shape.x * self.shape.y, OFF);
+    }
+
+    ///
+    /// Two digits, dimmed if needed
+    ///
+    fn render_digits(num: u32, dim: bool) -> Vec<u8> {
+        let digits = Bitmap {
+            width: 9,
+            height: 5,
+            pixels: ClockWidget::render_number(num.min(99)),
+        };
+        match dim {
+            true => digits.dimmed().pixels,
+            false => digits.pixels,
+        }
+    }
+
+    fn render_time(&mut self, reading: BatteryReading) {
+        let charging = reading.state == battery::State::Charging;
+        let remaining = match reading.state {
+            battery::State::Charging => reading.time_to_full,
+            battery::State::Discharging => reading.time_to_empty,
+            _ => None,
+        };
+
+        self.matrix = match remaining {
+            Some(secs) => {
+                let mins = (secs / 60.0).round() as u32;
+                let mut matrix = Self::render_digits(mins / 60, charging);
+                matrix.extend(vec![OFF; 9]);
+                matrix.extend(Self::render_digits(mins % 60, charging));
+                matrix
+            }
+            // No estimate (full, or still settling), show dashes
+            None => {
+                let mut matrix = vec![OFF; 9 * 11];
+                for y in [2, 8] {
+                    for x in [1, 2, 3, 5, 6, 7] {
+                        matrix[y * 9 + x] = ON_DIM;
+                    }
+                }
+                matrix
+            }
+        };
+    }
 
+    fn render_bar(&mut self, reading: BatteryReading) {
         // Update whether or not the device is charging
-        let bat_level_pct = battery_dev.state_of_charge()
-            .get::<battery::units::ratio::percent>();
+        let bat_level_pct = reading.percent();
         
-        let is_charging = battery_dev.state() == battery::State::Charging;
+        let is_charging = reading.state == battery::State::Charging;
 
         // Recreate the matrix
         self.matrix = vec![];
@@ -146,152 +325,2279 @@ impl UpdatableWidget for BatteryWidget {
             self.chrg_ind = !self.chrg_ind;
         }
     }
+}
+
+impl UpdatableWidget for BatteryWidget {
+    fn update(&mut self) {
+        self.read();
+        let Some(reading) = self.reading else {
+            self.render_missing();
+            return;
+        };
+
+        let charging = reading.state == battery::State::Charging;
+        match self.mode {
+            BatteryMode::Bar => self.render_bar(reading),
+            BatteryMode::Percent => {
+                self.matrix = render_percent(reading.percent().round() as u32);
+            }
+            BatteryMode::Time => self.render_time(reading),
+            BatteryMode::Power => {
+                let watts = reading.energy_rate.abs().round() as u32;
+                self.matrix = Self::render_digits(watts, charging);
+            }
+        }
+    }
 
-    fn get_matrix(&self) -> &Vec<u8> {
-        &self.matrix
+    fn preferred_size(&self) -> Shape {
+        self.shape
     }
 
-    fn get_shape(&self) -> &Shape {
-        &self.shape
+    fn render(&self, view: &mut View) {
+        view.draw(&self.matrix, self.shape.x);
     }
 }
 
 // -------- All Cores CPU Usage Widget --------
-/// Create a widget that displays the usage of all CPU cores, one per row.
+/// How the usage of the cores that share a bar is combined
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CpuAggregate {
+    /// Average usage of the cores
+    #[default]
+    Mean,
+    /// Usage of the busiest core
+    Max,
+}
+
+/// Create a widget that displays the usage of all CPU cores, one bar per core, or per
+/// group of cores when there are more cores than bars.
 pub struct AllCPUsWidget {
-    cpu_usages: Vec<u8>,
+    cpu_usages: Vec<f32>,
     merge_threads: bool,
+    aggregate: CpuAggregate,
     sys: sysinfo::System,
     matrix: Vec<u8>,
-    shape: Shape
+    shape: Shape,
 }
 
 impl AllCPUsWidget {
-    pub fn new(merge_threads: bool) -> AllCPUsWidget {
+    /// Rows used when the number of rows isn't set, cores are grouped beyond this
+    const DEFAULT_ROWS: usize = 16;
+
+    pub fn info() -> WidgetInfo {
+        let mut sys = sysinfo::System::new();
+        sys.refresh_cpu();
+
+        WidgetInfo {
+            name: "cpu",
+            title: "CPU Usage Indicator",
+            description: "Each row of LEDs is a bar of CPU usage. With more cores than rows, neighbouring cores share a bar.",
+            shape: Self::shape(sys.cpus().len(), false, None),
+            options: vec![
+                WidgetOption {
+                    name: "merge_threads",
+                    kind: "boolean",
+                    default: json!(false),
+                    description: "Combine pairs of threads into an 8 high vertical bar graph",
+                },
+                WidgetOption {
+                    name: "rows",
+                    kind: "number",
+                    default: json!(null),
+                    description: "Number of rows, one per core (up to 16) if not set",
+                },
+                WidgetOption {
+                    name: "aggregate",
+                    kind: "string",
+                    default: json!("mean"),
+                    description: "How cores sharing a bar are combined, mean or max",
+                },
+            ],
+        }
+    }
+
+    pub fn new(merge_threads: bool, rows: Option<usize>, aggregate: CpuAggregate) -> AllCPUsWidget {
         let mut newsys = sysinfo::System::new();
         newsys.refresh_cpu();
 
         println!("Initializing AllCPUsWidget");
 
         AllCPUsWidget {
-            shape: match merge_threads {
-                false => Shape {
-                    x: 9,
-                    y: newsys.cpus().len(),
-                },
-                true => Shape { x: 8, y: 8 },
-            },
-            cpu_usages: vec![0; newsys.cpus().len()],
+            shape: Self::shape(newsys.cpus().len(), merge_threads, rows),
+            cpu_usages: vec![0.0; newsys.cpus().len()],
             merge_threads,
+            aggregate,
             sys: newsys,
             matrix: vec![],
         }
     }
-}
 
-impl UpdatableWidget for AllCPUsWidget {
-    fn update(&mut self) {
-        // Refresh the cpu usage
-        self.sys.refresh_cpu();
+    ///
+    /// Shape of the widget for a number of logical CPUs. Merged threads are drawn as
+    /// up to 9 columns, 8 LEDs high. Otherwise there's one row per core, or `rows` rows
+    /// if set, never more rows than cores.
+    ///
+    fn shape(cpus: usize, merge_threads: bool, rows: Option<usize>) -> Shape {
+        let cpus = cpus.max(1);
+        if merge_threads {
+            Shape {
+                x: cpus.div_ceil(2).min(9),
+                y: 8,
+            }
+        } else {
+            Shape {
+                x: 9,
+                y: rows.unwrap_or(Self::DEFAULT_ROWS).clamp(1, 34).min(cpus),
+            }
+        }
+    }
 
-        for (idx, usage) in self.sys.cpus().iter().enumerate() {
-            self.cpu_usages[idx] = usage.cpu_usage().round() as u8;
-        }
-
-        // Create the matrix
-        let width = self.get_shape().x;
-		let height = self.get_shape().y;
-        self.matrix = vec![OFF; width * height];
-
-        if self.merge_threads {
-            for idy in 0..height {
-				let inverse_y = height - (idy + 1);
-                for (idx, chunk) in self.cpu_usages.chunks(2).enumerate() {
-                    let usage = (chunk[0] + chunk[1]) / 2;
-					if usage as usize >= inverse_y * 10 {
-						self.matrix[(idy * width) + idx] = ON_FULL;
-					}
+    ///
+    /// Split the cores into `bars` evenly sized groups of neighbouring cores, and
+    /// combine the usage of each group
+    ///
+    fn group(usages: &[f32], bars: usize, aggregate: CpuAggregate) -> Vec<f32> {
+        (0..bars)
+            .map(|i| {
+                let group = &usages[i * usages.len() / bars..(i + 1) * usages.len() / bars];
+                match aggregate {
+                    _ if group.is_empty() => 0.0,
+                    CpuAggregate::Mean => group.iter().sum::<f32>() / group.len() as f32,
+                    CpuAggregate::Max => group.iter().copied().fold(0.0, f32::max),
+                }
+            })
+            .collect()
+    }
+
+    ///
+    /// Draw the usage (in percent) of every core into a matrix of the given shape.
+    /// The first LED of each bar is always lit, so idle cores are still visible.
+    ///
+    fn render(
+        usages: &[f32],
+        shape: &Shape,
+        merge_threads: bool,
+        aggregate: CpuAggregate,
+    ) -> Vec<u8> {
+        let (width, height) = (shape.x, shape.y);
+        let mut matrix = vec![OFF; width * height];
+
+        if merge_threads {
+            let columns = Self::group(usages, width, aggregate);
+            for (idx, usage) in columns.into_iter().enumerate() {
+                for idy in 0..height {
+                    let inverse_y = height - (idy + 1);
+                    if usage >= (inverse_y * 100 / height) as f32 {
+                        matrix[(idy * width) + idx] = ON_FULL;
+                    }
                 }
             }
         } else {
-            for y in 0..16 {
+            let rows = Self::group(usages, height, aggregate);
+            for (y, usage) in rows.into_iter().enumerate() {
                 for x in 0..width {
-                    if x <= (self.cpu_usages[y] as f32 * width as f32 / 100f32) as usize {
-                        self.matrix[x + (y * width)] = ON_FULL;
+                    if x <= (usage * width as f32 / 100f32) as usize {
+                        matrix[x + (y * width)] = ON_FULL;
                     }
                 }
             }
         }
+
+        matrix
+    }
+}
+
+impl UpdatableWidget for AllCPUsWidget {
+    fn update(&mut self) {
+        // Refresh the cpu usage
+        self.sys.refresh_cpu();
+
+        for (idx, usage) in self.sys.cpus().iter().enumerate() {
+            self.cpu_usages[idx] = usage.cpu_usage().clamp(0.0, 100.0);
+        }
+
+        self.matrix = Self::render(
+            &self.cpu_usages,
+            &self.shape,
+            self.merge_threads,
+            self.aggregate,
+        );
     }
 
-    fn get_matrix(&self) -> &Vec<u8> {
-        &self.matrix
+    fn preferred_size(&self) -> Shape {
+        self.shape
     }
 
-    fn get_shape(&self) -> &Shape {
-        &self.shape
+    fn render(&self, view: &mut View) {
+        view.draw(&self.matrix, self.shape.x);
     }
 }
 
+// -------- Clock Widget --------
+/// How the clock shows the seconds
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ClockSeconds {
+    /// Seconds aren't shown
+    #[default]
+    None,
+    /// A bar below the time, filling up over the minute
+    Bar,
+    /// Two dim digits below the time
+    Digits,
+}
+
+/// What the clock shows
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ClockMode {
+    /// Hours above minutes
+    #[default]
+    Time,
+    /// Day of the month above the month
+    Date,
+}
+
 pub struct ClockWidget {
     matrix: Vec<u8>,
-    time: chrono::DateTime<Local>,
+    time: chrono::NaiveDateTime,
+    twelve_hour: bool,
+    seconds: ClockSeconds,
+    mode: ClockMode,
+    /// Timezone to show the time in, the system's timezone if None
+    timezone: Option<chrono_tz::Tz>,
+    shape: Shape,
 }
 
 impl ClockWidget {
-    pub fn new() -> Self {
+    pub fn info() -> WidgetInfo {
+        WidgetInfo {
+            name: "clock",
+            title: "Clock Widget",
+            description: "Displays the time in 24hr or 12hr format, or the date.",
+            shape: Shape { x: 9, y: 11 },
+            options: vec![
+                WidgetOption {
+                    name: "twelve_hour",
+                    kind: "boolean",
+                    default: json!(false),
+                    description: "12hr format, with a dot next to the hours in the afternoon",
+                },
+                WidgetOption {
+                    name: "seconds",
+                    kind: "string",
+                    default: json!("none"),
+                    description: "One of none, bar (9x13) or digits (9x17)",
+                },
+                WidgetOption {
+                    name: "mode",
+                    kind: "string",
+                    default: json!("time"),
+                    description: "time, or date to show the day above the month",
+                },
+                WidgetOption {
+                    name: "timezone",
+                    kind: "string",
+                    default: json!(null),
+                    description: "IANA timezone, e.g. America/New_York, the system's if not set",
+                },
+            ],
+        }
+    }
+
+    pub fn new(
+        twelve_hour: bool,
+        seconds: ClockSeconds,
+        mode: ClockMode,
+        timezone: Option<&str>,
+    ) -> Self {
         println!("Initializing ClockWidget");
-        let dt = chrono::offset::Local::now();
-        Self { time: dt, matrix: vec![] }
+
+        let timezone = timezone.and_then(|name| match name.parse::<chrono_tz::Tz>() {
+            Ok(tz) => Some(tz),
+            Err(e) => {
+                println!("ClockWidget: {}, using the system timezone", e);
+                None
+            }
+        });
+
+        // The date has no seconds
+        let seconds = match mode {
+            ClockMode::Date => ClockSeconds::None,
+            ClockMode::Time => seconds,
+        };
+        let height = match seconds {
+            ClockSeconds::None => 11,
+            ClockSeconds::Bar => 13,
+            ClockSeconds::Digits => 17,
+        };
+
+        let mut clock = Self {
+            time: Default::default(),
+            matrix: vec![],
+            twelve_hour,
+            seconds,
+            mode,
+            timezone,
+            shape: Shape { x: 9, y: height },
+        };
+        clock.time = clock.now();
+        clock
     }
 
-    fn render_digit(num: u32) -> &'static [u8] {
-        match num {
-            0 => DIGIT_0,
-            1 => DIGIT_1,
-            2 => DIGIT_2,
-            3 => DIGIT_3,
-            4 => DIGIT_4,
-            5 => DIGIT_5,
-            6 => DIGIT_6,
-            7 => DIGIT_7,
-            8 => DIGIT_8,
-            9 => DIGIT_9,
-            _ => DIGIT_0,
+    fn now(&self) -> chrono::NaiveDateTime {
+        match self.timezone {
+            Some(tz) => chrono::Utc::now().with_timezone(&tz).naive_local(),
+            None => Local::now().naive_local(),
         }
     }
 
-    fn render_number(num: u32) -> Vec<u8> {
-        let mut numrow = vec![0; 9 * 5];
-        let first_digit = Self::render_digit(num / 10);
-        let second_digit = Self::render_digit(num % 10);
-        for idx in 0..(9 * 5) {
-            let cell = match idx % 9 {
-                1 | 2 | 3 => first_digit[((idx / 9) * 3) + (idx % 9) - 1],
-                5 | 6 | 7 => second_digit[((idx / 9) * 3) + idx % 9 - 5],
-                _ => OFF,
-            };
-            numrow[idx] = cell;
+    ///
+    /// Draw the clock for the given wall clock time
+    ///
+    fn render(&self, time: chrono::NaiveDateTime) -> Vec<u8> {
+        let (top, bottom) = match self.mode {
+            ClockMode::Time if self.twelve_hour => (time.hour12().1, time.minute()),
+            ClockMode::Time => (time.hour(), time.minute()),
+            ClockMode::Date => (time.day(), time.month()),
+        };
+
+        let mut matrix = Vec::with_capacity(self.shape.x * self.shape.y);
+        matrix.extend(Self::render_number(top));
+
+        if self.mode == ClockMode::Time && self.twelve_hour {
+            // No leading zero on the hour, and a dot after it in the afternoon
+            if top < 10 {
+                for row in matrix.chunks_mut(9) {
+                    row[1..4].fill(OFF);
+                }
+            }
+            if time.hour12().0 {
+                matrix[9 * 4 + 8] = ON_FULL;
+            }
+        }
+
+        matrix.extend(vec![OFF; 9]);
+        matrix.extend(Self::render_number(bottom));
+
+        match self.seconds {
+            ClockSeconds::None => {}
+            ClockSeconds::Bar => {
+                matrix.extend(vec![OFF; 9 * 2]);
+                let bar = (0, self.shape.y - 1, 9, 1);
+                let frac = time.second() as f32 / 59.0;
+                fill_bar(&mut matrix, 9, bar, frac, false, ON_DIM);
+            }
+            ClockSeconds::Digits => {
+                matrix.extend(vec![OFF; 9]);
+                let digits = Self::render_number(time.second());
+                matrix.extend(digits.into_iter().map(|led| led.min(ON_DIM)));
+            }
         }
-        numrow
+
+        matrix
+    }
+
+    ///
+    /// Two digits of the 3x5 font in a 9x5 block, with a blank column on either side
+    ///
+    fn render_number(num: u32) -> Vec<u8> {
+        let mut numrow = Bitmap::new(9, 5);
+        let digits = Font::small().render(&format!("{:02}", num % 100));
+        numrow.blit(&digits, 1, 0);
+        numrow.pixels
     }
 }
 
 impl UpdatableWidget for ClockWidget {
     fn update(&mut self) {
-        self.time = chrono::offset::Local::now();
-        self.matrix = Vec::with_capacity(9 * 11);
-        self.matrix.extend(Self::render_number(self.time.hour()));
-        self.matrix.extend(vec![OFF; 9]);
-        self.matrix.extend(Self::render_number(self.time.minute()));
-        
+        self.time = self.now();
+        self.matrix = self.render(self.time);
+    }
+
+    fn preferred_size(&self) -> Shape {
+        self.shape
+    }
+
+    fn render(&self, view: &mut View) {
+        view.draw(&self.matrix, self.shape.x);
+    }
+}
+
+// -------- Memory Widget --------
+/// How the memory widget displays usage
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoryStyle {
+    /// Bars filling from left to right
+    #[default]
+    Horizontal,
+    /// Bars filling from the bottom up
+    Vertical,
+    /// Percent used, as two digits
+    Percent,
+}
+
+/// Create a widget that displays RAM usage, and optionally swap usage
+pub struct MemoryWidget {
+    sys: sysinfo::System,
+    style: MemoryStyle,
+    show_swap: bool,
+    matrix: Vec<u8>,
+    shape: Shape,
+}
+
+impl MemoryWidget {
+    pub fn info() -> WidgetInfo {
+        WidgetInfo {
+            name: "memory",
+            title: "Memory Usage Indicator",
+            description: "Used RAM (bright) and optionally swap (dim), as bars or a percentage.",
+            shape: Self::default_shape(MemoryStyle::Horizontal, false),
+            options: vec![
+                WidgetOption {
+                    name: "style",
+                    kind: "string",
+                    default: json!("horizontal"),
+                    description: "One of horizontal, vertical or percent",
+                },
+                WidgetOption {
+                    name: "swap",
+                    kind: "boolean",
+                    default: json!(false),
+                    description: "Also show swap usage",
+                },
+                WidgetOption {
+                    name: "width",
+                    kind: "number",
+                    default: json!(null),
+                    description: "Width of the bars, ignored for percent",
+                },
+                WidgetOption {
+                    name: "height",
+                    kind: "number",
+                    default: json!(null),
+                    description: "Height of the bars, ignored for percent",
+                },
+            ],
+        }
+    }
+
+    fn default_shape(style: MemoryStyle, show_swap: bool) -> Shape {
+        match (style, show_swap) {
+            (MemoryStyle::Horizontal, false) => Shape { x: 9, y: 2 },
+            (MemoryStyle::Horizontal, true) => Shape { x: 9, y: 5 },
+            (MemoryStyle::Vertical, false) => Shape { x: 4, y: 10 },
+            (MemoryStyle::Vertical, true) => Shape { x: 9, y: 10 },
+            (MemoryStyle::Percent, false) => Shape { x: 9, y: 5 },
+            (MemoryStyle::Percent, true) => Shape { x: 9, y: 11 },
+        }
+    }
+
+    pub fn new(
+        style: MemoryStyle,
+        show_swap: bool,
+        width: Option<usize>,
+        height: Option<usize>,
+    ) -> MemoryWidget {
+        println!("Initializing MemoryWidget");
+
+        let mut shape = Self::default_shape(style, show_swap);
+        if style != MemoryStyle::Percent {
+            shape.x = width.unwrap_or(shape.x).clamp(1, 9);
+            shape.y = height.unwrap_or(shape.y).clamp(1, 34);
+        }
+
+        MemoryWidget {
+            sys: sysinfo::System::new(),
+            style,
+            show_swap,
+            matrix: vec![],
+            shape,
+        }
+    }
+
+    ///
+    /// Split the widget into the area for RAM and the area for swap, with a 1 LED
+    /// gap in between when there's room for it
+    ///
+    fn areas(&self) -> [(usize, usize, usize, usize); 2] {
+        let (w, h) = (self.shape.x, self.shape.y);
+        if !self.show_swap {
+            return [(0, 0, w, h), (0, 0, 0, 0)];
+        }
+
+        let vertical = self.style == MemoryStyle::Vertical;
+        let len = if vertical { w } else { h };
+        let gap = if len >= 3 { 1 } else { 0 };
+        let first = (len - gap).div_ceil(2);
+        let second = len - gap - first;
+
+        if vertical {
+            [(0, 0, first, h), (first + gap, 0, second, h)]
+        } else {
+            [(0, 0, w, first), (0, first + gap, w, second)]
+        }
+    }
+
+    /// Redraw the matrix for the fraction of RAM and swap in use
+    fn render_usage(&mut self, ram: f32, swap: f32) {
+        self.matrix = vec![OFF; self.shape.x * self.shape.y];
+
+        match self.style {
+            MemoryStyle::Percent => {
+                let pct = |frac: f32| (frac * 100.0).round() as u32;
+                self.matrix = render_percent(pct(ram));
+                if self.show_swap {
+                    self.matrix.extend(vec![OFF; 9]);
+                    let swap = render_percent(pct(swap));
+                    self.matrix
+                        .extend(swap.into_iter().map(|led| led.min(ON_DIM)));
+                }
+            }
+            _ => {
+                let vertical = self.style == MemoryStyle::Vertical;
+                let stride = self.shape.x;
+                let [ram_area, swap_area] = self.areas();
+                fill_bar(&mut self.matrix, stride, ram_area, ram, vertical, ON_FULL);
+                if self.show_swap {
+                    fill_bar(&mut self.matrix, stride, swap_area, swap, vertical, ON_DIM);
+                }
+            }
+        }
+    }
+}
+
+impl UpdatableWidget for MemoryWidget {
+    fn update(&mut self) {
+        self.sys.refresh_memory();
+
+        let ratio = |used: u64, total: u64| match total {
+            0 => 0.0,
+            _ => used as f32 / total as f32,
+        };
+        let ram = ratio(self.sys.used_memory(), self.sys.total_memory());
+        let swap = ratio(self.sys.used_swap(), self.sys.total_swap());
+        self.render_usage(ram, swap);
+    }
+
+    fn preferred_size(&self) -> Shape {
+        self.shape
+    }
+
+    fn render(&self, view: &mut View) {
+        view.draw(&self.matrix, self.shape.x);
+    }
+}
+
+// -------- Disk Usage Widget --------
+/// Create a widget that displays how full one or more disks are, as a stack of bars.
+/// Bars of disks that are fuller than the warning threshold blink.
+pub struct DiskWidget {
+    disks: sysinfo::Disks,
+    mounts: Vec<String>,
+    warn_pct: f32,
+    bar_height: usize,
+    blink: bool,
+    matrix: Vec<u8>,
+    shape: Shape,
+}
+
+impl DiskWidget {
+    pub fn info() -> WidgetInfo {
+        WidgetInfo {
+            name: "disk",
+            title: "Disk Usage Indicator",
+            description:
+                "One bar per mount point showing how full the disk is, blinking when almost full.",
+            shape: Shape { x: 9, y: 2 },
+            options: vec![
+                WidgetOption {
+                    name: "mounts",
+                    kind: "array",
+                    default: json!([Self::default_mount()]),
+                    description: "Mount points to display, one bar each from top to bottom",
+                },
+                WidgetOption {
+                    name: "warn_pct",
+                    kind: "number",
+                    default: json!(90.0),
+                    description: "Usage percentage above which the bar blinks",
+                },
+                WidgetOption {
+                    name: "bar_height",
+                    kind: "number",
+                    default: json!(2),
+                    description: "Height of each bar",
+                },
+            ],
+        }
+    }
+
+    pub fn default_mount() -> &'static str {
+        if cfg!(windows) {
+            "C:\\"
+        } else {
+            "/"
+        }
+    }
+
+    pub fn new(mut mounts: Vec<String>, warn_pct: f32, bar_height: usize) -> DiskWidget {
+        println!("Initializing DiskWidget");
+
+        if mounts.is_empty() {
+            println!(
+                "DiskWidget: No mounts given, showing {}",
+                Self::default_mount()
+            );
+            mounts.push(Self::default_mount().to_string());
+        }
+
+        let disks = sysinfo::Disks::new_with_refreshed_list();
+        for mount in mounts.iter() {
+            if !disks
+                .list()
+                .iter()
+                .any(|d| d.mount_point().as_os_str() == mount.as_str())
+            {
+                println!("DiskWidget: No disk mounted at {}", mount);
+            }
+        }
+
+        // Bars are separated by one empty row, and must fit on the matrix
+        let bar_height = bar_height.clamp(1, 34);
+        let max_bars = (34 + 1) / (bar_height + 1);
+        if mounts.len() > max_bars {
+            println!(
+                "DiskWidget: Only {} bars fit, not showing {}",
+                max_bars,
+                mounts[max_bars..].join(", ")
+            );
+            mounts.truncate(max_bars);
+        }
+        let height = (mounts.len() * (bar_height + 1)).saturating_sub(1);
+
+        DiskWidget {
+            disks,
+            mounts,
+            warn_pct,
+            bar_height,
+            blink: false,
+            matrix: vec![],
+            shape: Shape { x: 9, y: height },
+        }
+    }
+
+    /// Redraw the matrix for the fraction of each disk in use, in the same order as the mounts
+    fn render_usage(&mut self, used: &[f32]) {
+        self.blink = !self.blink;
+        self.matrix = vec![OFF; self.shape.x * self.shape.y];
+
+        for (idx, &used) in used.iter().enumerate() {
+            // Blink by skipping every other frame
+            if used * 100.0 >= self.warn_pct && self.blink {
+                continue;
+            }
+
+            let y = idx * (self.bar_height + 1);
+            let area = (0, y, self.shape.x, self.bar_height);
+            fill_bar(&mut self.matrix, self.shape.x, area, used, false, ON_FULL);
+        }
+    }
+}
+
+impl UpdatableWidget for DiskWidget {
+    fn update(&mut self) {
+        self.disks.refresh();
+
+        let used: Vec<f32> = self
+            .mounts
+            .iter()
+            .map(|mount| {
+                self.disks
+                    .list()
+                    .iter()
+                    .find(|d| d.mount_point().as_os_str() == mount.as_str())
+                    .filter(|d| d.total_space() > 0)
+                    .map(|d| 1.0 - (d.available_space() as f32 / d.total_space() as f32))
+                    .unwrap_or(0.0)
+            })
+            .collect();
+        self.render_usage(&used);
     }
 
-    fn get_matrix(&self) -> &Vec<u8> {
-        &self.matrix
+    fn preferred_size(&self) -> Shape {
+        self.shape
     }
 
-    fn get_shape(&self) -> &Shape {
-        return &Shape { x: 9, y: 11 };
+    fn render(&self, view: &mut View) {
+        view.draw(&self.matrix, self.shape.x);
+    }
+}
+
+// -------- Network Traffic Widget --------
+/// Create a widget that displays recent network traffic as a graph scrolling from right
+/// to left. Download grows up from the middle (bright), upload grows down (dim), both
+/// scaled to the busiest sample still on screen.
+pub struct NetworkWidget {
+    networks: sysinfo::Networks,
+    interface: Option<String>,
+    /// Download and upload rates in bytes per second, newest last
+    history: VecDeque<(f32, f32)>,
+    last_sample: Instant,
+    shape: Shape,
+}
+
+impl NetworkWidget {
+    /// Traffic below this rate (bytes/s) isn't scaled up to fill the graph
+    const MIN_SCALE: f32 = 1024.0;
+
+    pub fn info() -> WidgetInfo {
+        WidgetInfo {
+            name: "network",
+            title: "Network Traffic Graph",
+            description: "Scrolling graph of download (up, bright) and upload (down, dim) speed.",
+            shape: Shape { x: 9, y: 10 },
+            options: vec![
+                WidgetOption {
+                    name: "interface",
+                    kind: "string",
+                    default: json!(null),
+                    description: "Network interface to monitor, all but loopback if not set",
+                },
+                WidgetOption {
+                    name: "width",
+                    kind: "number",
+                    default: json!(9),
+                    description: "Width of the graph, one sample per column",
+                },
+                WidgetOption {
+                    name: "height",
+                    kind: "number",
+                    default: json!(10),
+                    description: "Height of the graph, split between download and upload",
+                },
+            ],
+        }
+    }
+
+    pub fn new(
+        interface: Option<String>,
+        width: Option<usize>,
+        height: Option<usize>,
+    ) -> NetworkWidget {
+        println!("Initializing NetworkWidget");
+
+        let networks = sysinfo::Networks::new_with_refreshed_list();
+        if let Some(name) = &interface {
+            if !networks.list().contains_key(name) {
+                println!("NetworkWidget: No network interface named {}", name);
+            }
+        }
+
+        let shape = Shape {
+            x: width.unwrap_or(9).clamp(1, 9),
+            y: height.unwrap_or(10).clamp(2, 34),
+        };
+
+        NetworkWidget {
+            networks,
+            interface,
+            history: VecDeque::from(vec![(0.0, 0.0); shape.x]),
+            last_sample: Instant::now(),
+            shape,
+        }
+    }
+
+    fn is_loopback(name: &str) -> bool {
+        name == "lo" || name.starts_with("lo0") || name.contains("Loopback")
+    }
+
+    /// Bytes received and transmitted since the previous refresh
+    fn sample(&self) -> (u64, u64) {
+        self.networks
+            .list()
+            .iter()
+            .filter(|(name, _)| match &self.interface {
+                Some(iface) => *name == iface,
+                None => !Self::is_loopback(name),
+            })
+            .fold((0, 0), |(rx, tx), (_, data)| {
+                (rx + data.received(), tx + data.transmitted())
+            })
+    }
+
+    /// Scroll the graph by one sample, in bytes per second
+    fn push(&mut self, rx: f32, tx: f32) {
+        self.history.pop_front();
+        self.history.push_back((rx, tx));
+    }
+}
+
+impl UpdatableWidget for NetworkWidget {
+    fn update(&mut self) {
+        self.networks.refresh();
+
+        let elapsed = self.last_sample.elapsed().as_secs_f32().max(f32::EPSILON);
+        self.last_sample = Instant::now();
+
+        let (rx, tx) = self.sample();
+        self.push(rx as f32 / elapsed, tx as f32 / elapsed);
+    }
+
+    fn preferred_size(&self) -> Shape {
+        self.shape
+    }
+
+    fn min_size(&self) -> Shape {
+        Shape { x: 1, y: 2 }
+    }
+
+    fn render(&self, view: &mut View) {
+        // A narrower view only fits the newest samples
+        let (w, h) = (view.width().min(self.history.len()), view.height());
+        let shown = self.history.range(self.history.len() - w..);
+
+        let scale = shown
+            .clone()
+            .fold(Self::MIN_SCALE, |max, &(rx, tx)| max.max(rx).max(tx));
+
+        let rx_h = h.div_ceil(2);
+        let tx_h = h - rx_h;
+        let mut matrix = vec![OFF; w * h];
+
+        for (x, &(rx, tx)) in shown.enumerate() {
+            let rx_area = (x, 0, 1, rx_h);
+            fill_bar(&mut matrix, w, rx_area, rx / scale, true, ON_FULL);
+
+            // Upload hangs down from the middle, so fill from the top of its half
+            let lit = ((tx / scale).clamp(0.0, 1.0) * tx_h as f32).round() as usize;
+            for y in rx_h..(rx_h + lit) {
+                matrix[y * w + x] = ON_DIM;
+            }
+        }
+
+        view.draw(&matrix, w);
+    }
+}
+
+// -------- Overall CPU Usage Widget --------
+/// Create a widget that displays the overall CPU usage as a graph scrolling from right
+/// to left, optionally with the current usage as a percentage above it.
+pub struct CpuHistoryWidget {
+    sys: sysinfo::System,
+    show_percent: bool,
+    /// Overall usage in percent, newest last
+    history: VecDeque<f32>,
+    shape: Shape,
+}
+
+impl CpuHistoryWidget {
+    /// Rows taken up by the percentage and the gap below it
+    const PERCENT_ROWS: usize = 6;
+
+    pub fn info() -> WidgetInfo {
+        WidgetInfo {
+            name: "cpu_history",
+            title: "Overall CPU Usage Graph",
+            description: "Scrolling graph of the usage of all CPU cores combined.",
+            shape: Shape { x: 9, y: 10 },
+            options: vec![
+                WidgetOption {
+                    name: "percent",
+                    kind: "boolean",
+                    default: json!(false),
+                    description: "Show the current usage as two digits above the graph",
+                },
+                WidgetOption {
+                    name: "width",
+                    kind: "number",
+                    default: json!(9),
+                    description: "Width of the graph, one sample per column (9 with percent)",
+                },
+                WidgetOption {
+                    name: "height",
+                    kind: "number",
+                    default: json!(10),
+                    description: "Height of the graph, not counting the percentage",
+                },
+            ],
+        }
+    }
+
+    pub fn new(show_percent: bool, width: Option<usize>, height: Option<usize>) -> Self {
+        println!("Initializing CpuHistoryWidget");
+
+        let mut sys = sysinfo::System::new();
+        sys.refresh_cpu_usage();
+
+        // The digits are always 9 wide
+        let width = match show_percent {
+            true => 9,
+            false => width.unwrap_or(9).clamp(1, 9),
+        };
+        let extra = if show_percent { Self::PERCENT_ROWS } else { 0 };
+        let height = height.unwrap_or(10).clamp(1, 34 - extra) + extra;
+
+        CpuHistoryWidget {
+            sys,
+            show_percent,
+            history: VecDeque::from(vec![0.0; width]),
+            shape: Shape {
+                x: width,
+                y: height,
+            },
+        }
+    }
+
+    /// Scroll the graph by one sample, in percent
+    fn push(&mut self, usage: f32) {
+        self.history.pop_front();
+        self.history.push_back(usage);
+    }
+}
+
+impl UpdatableWidget for CpuHistoryWidget {
+    fn update(&mut self) {
+        self.sys.refresh_cpu_usage();
+        let usage = self.sys.global_cpu_info().cpu_usage();
+        self.push(usage);
+    }
+
+    fn preferred_size(&self) -> Shape {
+        self.shape
+    }
+
+    fn min_size(&self) -> Shape {
+        match self.show_percent {
+            true => Shape {
+                x: 9,
+                y: Self::PERCENT_ROWS + 1,
+            },
+            false => Shape { x: 1, y: 1 },
+        }
+    }
+
+    fn render(&self, view: &mut View) {
+        // A narrower view only fits the newest samples
+        let (w, h) = (view.width().min(self.history.len()), view.height());
+        let shown = self.history.range(self.history.len() - w..);
+        let mut matrix = vec![OFF; w * h];
+
+        let top = match self.show_percent {
+            true => Self::PERCENT_ROWS.min(h),
+            false => 0,
+        };
+        for (x, usage) in shown.enumerate() {
+            let area = (x, top, 1, h - top);
+            fill_bar(&mut matrix, w, area, usage / 100.0, true, ON_FULL);
+        }
+        view.draw(&matrix, w);
+
+        if self.show_percent {
+            let usage = self.history.back().copied().unwrap_or(0.0);
+            view.draw(&render_percent(usage.round() as u32), 9);
+        }
+    }
+}
+
+// -------- Temperature Widget --------
+/// Create a widget that displays the temperature of a sensor as a thermometer, and
+/// optionally the fan speed as a bar next to it. The thermometer is dim while cool,
+/// bright above the warning threshold and blinks above the critical threshold.
+pub struct TemperatureWidget {
+    components: sysinfo::Components,
+    sensor: Option<usize>,
+    warn: f32,
+    crit: f32,
+    /// Directory with the hwmon devices to read the fan speed from, if enabled
+    hwmon: Option<PathBuf>,
+    max_rpm: u32,
+    blink: bool,
+    matrix: Vec<u8>,
+    shape: Shape,
+}
+
+impl TemperatureWidget {
+    /// Temperature at the bottom of the thermometer
+    const MIN_TEMP: f32 = 30.0;
+    /// Where hwmon devices live on Linux
+    pub const HWMON_ROOT: &'static str = "/sys/class/hwmon";
+    /// Sensors picked when none is configured, in order of preference
+    const DEFAULT_SENSORS: [&'static str; 3] = ["tctl", "package", "cpu"];
+
+    pub fn info() -> WidgetInfo {
+        WidgetInfo {
+            name: "temperature",
+            title: "Temperature Indicator",
+            description: "Thermometer for a temperature sensor, with an optional fan speed bar.",
+            shape: Shape { x: 3, y: 12 },
+            options: vec![
+                WidgetOption {
+                    name: "sensor",
+                    kind: "string",
+                    default: json!(null),
+                    description: "Part of the sensor label, e.g. Tctl, edge or Composite",
+                },
+                WidgetOption {
+                    name: "warn",
+                    kind: "number",
+                    default: json!(70.0),
+                    description: "Temperature (C) above which the thermometer is bright",
+                },
+                WidgetOption {
+                    name: "crit",
+                    kind: "number",
+                    default: json!(90.0),
+                    description: "Temperature (C) above which the thermometer blinks",
+                },
+                WidgetOption {
+                    name: "fan",
+                    kind: "boolean",
+                    default: json!(false),
+                    description: "Show the fastest fan's speed from hwmon (Linux only)",
+                },
+                WidgetOption {
+                    name: "height",
+                    kind: "number",
+                    default: json!(12),
+                    description: "Height of the thermometer",
+                },
+            ],
+        }
+    }
+
+    pub fn new(
+        sensor: Option<&str>,
+        warn: f32,
+        crit: f32,
+        hwmon: Option<PathBuf>,
+        height: Option<usize>,
+    ) -> TemperatureWidget {
+        println!("Initializing TemperatureWidget");
+
+        let components = sysinfo::Components::new_with_refreshed_list();
+        let labels: Vec<&str> = components.list().iter().map(|c| c.label()).collect();
+        let found = Self::find_sensor(&labels, sensor);
+        match found {
+            Some(idx) => println!("TemperatureWidget: Using sensor {}", labels[idx]),
+            None => println!("TemperatureWidget: No temperature sensor found"),
+        }
+
+        let shape = Shape {
+            x: if hwmon.is_some() { 7 } else { 3 },
+            y: height.unwrap_or(12).clamp(4, 34),
+        };
+
+        TemperatureWidget {
+            components,
+            sensor: found,
+            warn,
+            crit: crit.max(warn),
+            hwmon,
+            max_rpm: 0,
+            blink: false,
+            matrix: vec![],
+            shape,
+        }
+    }
+
+    ///
+    /// Find the sensor whose label contains `wanted` (ignoring case), or the CPU
+    /// temperature if nothing is wanted
+    ///
+    fn find_sensor(labels: &[&str], wanted: Option<&str>) -> Option<usize> {
+        let find = |wanted: &str| {
+            let wanted = wanted.to_lowercase();
+            labels
+                .iter()
+                .position(|label| label.to_lowercase().contains(&wanted))
+        };
+
+        match wanted {
+            Some(wanted) => find(wanted),
+            None => Self::DEFAULT_SENSORS
+                .iter()
+                .find_map(|wanted| find(wanted))
+                .or(if labels.is_empty() { None } else { Some(0) }),
+        }
+    }
+
+    ///
+    /// Read the speed of the fastest fan from every `fan*_input` of every hwmon
+    /// device under `root`
+    ///
+    fn read_fan_rpm(root: &Path) -> Option<u32> {
+        fs::read_dir(root)
+            .ok()?
+            .flatten()
+            .filter_map(|dev| fs::read_dir(dev.path()).ok())
+            .flatten()
+            .flatten()
+            .filter(|entry| {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                name.starts_with("fan") && name.ends_with("_input")
+            })
+            .filter_map(|entry| fs::read_to_string(entry.path()).ok()?.trim().parse().ok())
+            .max()
+    }
+
+    ///
+    /// Draw the thermometer, a 3x2 bulb with a tube above it filled up to the
+    /// temperature, and the fan speed bar (0.0 - 1.0) next to it
+    ///
+    fn render(&self, temp: Option<f32>, fan: Option<f32>) -> Vec<u8> {
+        let (w, h) = (self.shape.x, self.shape.y);
+        let mut matrix = vec![OFF; w * h];
+        let tube_h = h - 2;
+
+        let brightness = match temp {
+            Some(temp) if temp >= self.crit && self.blink => OFF,
+            Some(temp) if temp >= self.warn => ON_FULL,
+            Some(_) => ON_DIM,
+            None => OFF,
+        };
+        let frac = temp.map_or(0.0, |t| (t - Self::MIN_TEMP) / (self.crit - Self::MIN_TEMP));
+        fill_bar(&mut matrix, w, (1, 0, 1, tube_h), frac, true, brightness);
+        fill_bar(&mut matrix, w, (0, tube_h, 3, 2), 1.0, true, brightness);
+
+        // Without a sensor, the bulb is drawn hollow
+        if temp.is_none() {
+            fill_bar(&mut matrix, w, (0, tube_h, 3, 2), 1.0, true, ON_DIM);
+            matrix[(h - 1) * w + 1] = OFF;
+        }
+
+        if let Some(frac) = fan {
+            fill_bar(&mut matrix, w, (4, 0, 3, h), frac, true, ON_DIM);
+        }
+
+        matrix
+    }
+}
+
+impl UpdatableWidget for TemperatureWidget {
+    fn update(&mut self) {
+        self.components.refresh();
+        self.blink = !self.blink;
+
+        let temp = self
+            .sensor
+            .and_then(|idx| self.components.list().get(idx))
+            .map(|c| c.temperature());
+
+        let fan = self.hwmon.as_ref().map(|root| {
+            let rpm = Self::read_fan_rpm(root).unwrap_or(0);
+            // Scale to the fastest the fans have been seen spinning
+            self.max_rpm = self.max_rpm.max(rpm).max(1000);
+            rpm as f32 / self.max_rpm as f32
+        });
+
+        self.matrix = self.render(temp, fan);
+    }
+
+    fn preferred_size(&self) -> Shape {
+        self.shape
+    }
+
+    fn render(&self, view: &mut View) {
+        view.draw(&self.matrix, self.shape.x);
+    }
+}
+
+// -------- Text Widget --------
+/// Which of the built-in fonts to draw text with
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+pub enum FontChoice {
+    #[default]
+    #[serde(rename = "3x5")]
+    Small,
+    #[serde(rename = "5x7")]
+    Large,
+}
+
+impl FontChoice {
+    pub fn font(self) -> &'static Font {
+        match self {
+            FontChoice::Small => Font::small(),
+            FontChoice::Large => Font::large(),
+        }
+    }
+}
+
+/// Create a widget that displays a line of text, scrolling it like a marquee when
+/// it's longer than the widget
+pub struct TextWidget {
+    text: Bitmap,
+    /// Whether the text runs down the module instead of across it
+    rotated: bool,
+    /// LEDs to scroll by on every update
+    step: usize,
+    offset: usize,
+    matrix: Vec<u8>,
+    shape: Shape,
+}
+
+impl TextWidget {
+    /// Empty LEDs between the end of the text and its start coming around again
+    const GAP: usize = 3;
+
+    pub fn info() -> WidgetInfo {
+        WidgetInfo {
+            name: "text",
+            title: "Text",
+            description: "A line of text, scrolling when it doesn't fit.",
+            shape: Shape { x: 9, y: 5 },
+            options: vec![
+                WidgetOption {
+                    name: "text",
+                    kind: "string",
+                    default: json!(""),
+                    description: "Text to display",
+                },
+                WidgetOption {
+                    name: "font",
+                    kind: "string",
+                    default: json!("3x5"),
+                    description: "3x5 or 5x7",
+                },
+                WidgetOption {
+                    name: "font_file",
+                    kind: "string",
+                    default: json!(null),
+                    description: "BDF, PSF1 or PSF2 font file to use instead",
+                },
+                WidgetOption {
+                    name: "font_height",
+                    kind: "number",
+                    default: json!(null),
+                    description: "Height to scale the font file to, in LEDs",
+                },
+                WidgetOption {
+                    name: "rotated",
+                    kind: "boolean",
+                    default: json!(false),
+                    description: "Run the text down the module, read with the laptop turned",
+                },
+                WidgetOption {
+                    name: "length",
+                    kind: "number",
+                    default: json!(null),
+                    description: "Width (or height when rotated), the whole module if not set",
+                },
+                WidgetOption {
+                    name: "scroll",
+                    kind: "number",
+                    default: json!(1),
+                    description: "LEDs to scroll by on every update, 0 to not scroll",
+                },
+            ],
+        }
+    }
+
+    pub fn new(text: &str, font: &Font, rotated: bool, length: Option<usize>, step: usize) -> Self {
+        println!("Initializing TextWidget");
+
+        let font = match rotated != font.is_rotated() {
+            true => font.rotated(),
+            false => font.clone(),
+        };
+        let text = font.render(text);
+
+        let shape = match rotated {
+            false => Shape {
+                x: length.unwrap_or(9).clamp(1, 9),
+                y: text.height.min(34),
+            },
+            true => Shape {
+                x: text.width.min(9),
+                y: length.unwrap_or(34).clamp(1, 34),
+            },
+        };
+
+        TextWidget {
+            text,
+            rotated,
+            step,
+            offset: 0,
+            matrix: vec![],
+            shape,
+        }
+    }
+
+    ///
+    /// Draw the text scrolled by `offset` LEDs. Text that fits isn't scrolled.
+    ///
+    fn render(&self, offset: usize) -> Vec<u8> {
+        let mut out = Bitmap::new(self.shape.x, self.shape.y);
+        let (length, room) = match self.rotated {
+            false => (self.text.width, self.shape.x),
+            true => (self.text.height, self.shape.y),
+        };
+
+        // The text is drawn twice, so its start follows right behind its end
+        let period = length + Self::GAP;
+        let shift = (offset % period) as isize;
+        let starts = match length > room {
+            true => vec![-shift, period as isize - shift],
+            false => vec![0],
+        };
+        for start in starts {
+            match self.rotated {
+                false => out.blit(&self.text, start, 0),
+                true => out.blit(&self.text, 0, start),
+            }
+        }
+
+        out.pixels
+    }
+}
+
+impl UpdatableWidget for TextWidget {
+    fn update(&mut self) {
+        self.matrix = self.render(self.offset);
+        self.offset = self.offset.wrapping_add(self.step);
+    }
+
+    fn preferred_size(&self) -> Shape {
+        self.shape
+    }
+
+    fn render(&self, view: &mut View) {
+        view.draw(&self.matrix, self.shape.x);
+    }
+}
+
+// -------- Image Widget --------
+/// Create a widget that displays an image file (PNG, PGM/PBM, or an animated GIF),
+/// scaled down to fit its region. Animated GIFs play at their own speed, skipping
+/// frames that are shorter than an update.
+pub struct ImageWidget {
+    /// Every frame, already scaled and converted to LED brightnesses
+    frames: Vec<Vec<u8>>,
+    /// How long each frame is shown for
+    delays: Vec<Duration>,
+    /// Frame currently shown, and for how long it has been shown
+    frame: usize,
+    elapsed: Duration,
+    last_update: Instant,
+    dither: bool,
+    matrix: Vec<u8>,
+    shape: Shape,
+}
+
+impl ImageWidget {
+    pub fn info() -> WidgetInfo {
+        WidgetInfo {
+            name: "image",
+            title: "Image",
+            description: "A picture or animated GIF, scaled to fit.",
+            shape: Shape { x: 9, y: 34 },
+            options: vec![
+                WidgetOption {
+                    name: "path",
+                    kind: "string",
+                    default: json!(""),
+                    description: "PNG, PGM/PBM or GIF file to display",
+                },
+                WidgetOption {
+                    name: "width",
+                    kind: "number",
+                    default: json!(9),
+                    description: "Largest width to scale the image to",
+                },
+                WidgetOption {
+                    name: "height",
+                    kind: "number",
+                    default: json!(34),
+                    description: "Largest height to scale the image to",
+                },
+                WidgetOption {
+                    name: "dither",
+                    kind: "boolean",
+                    default: json!(false),
+                    description: "Only use fully on and off LEDs, dithering the shades in between",
+                },
+            ],
+        }
+    }
+
+    /// How long GIF frames without a delay are shown for, the same as web browsers
+    const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+
+    pub fn new(path: &Path, width: Option<usize>, height: Option<usize>, dither: bool) -> Self {
+        println!("Initializing ImageWidget");
+
+        let region = Shape {
+            x: width.unwrap_or(9).clamp(1, 9),
+            y: height.unwrap_or(34).clamp(1, 34),
+        };
+        let (frames, delays, shape) = match Self::load(path) {
+            Ok(images) => {
+                let shape = images
+                    .first()
+                    .map(|(img, _)| Self::fit(img, &region))
+                    .unwrap_or(Shape { x: 0, y: 0 });
+                let frames = images
+                    .iter()
+                    .map(|(img, _)| Self::convert(img, &shape, dither))
+                    .collect();
+                let delays = images.iter().map(|&(_, delay)| delay).collect();
+                (frames, delays, shape)
+            }
+            // Nothing to show, but keep the other widgets going
+            Err(e) => {
+                println!("Unable to load image {}: {}", path.display(), e);
+                (vec![], vec![], Shape { x: 0, y: 0 })
+            }
+        };
+
+        ImageWidget {
+            frames,
+            delays,
+            frame: 0,
+            elapsed: Duration::ZERO,
+            last_update: Instant::now(),
+            dither,
+            matrix: vec![],
+            shape,
+        }
+    }
+
+    ///
+    /// Read every frame of an image and how long it is shown for, a single frame
+    /// unless it's an animated GIF
+    ///
+    fn load(path: &Path) -> image::ImageResult<Vec<(DynamicImage, Duration)>> {
+        let reader = ImageReader::open(path)?.with_guessed_format()?;
+        if reader.format() != Some(ImageFormat::Gif) {
+            return Ok(vec![(reader.decode()?, Duration::ZERO)]);
+        }
+
+        let decoder = GifDecoder::new(BufReader::new(fs::File::open(path)?))?;
+        let frames = decoder.into_frames().collect_frames()?;
+        Ok(frames
+            .into_iter()
+            .map(|frame| {
+                let delay = match Duration::from(frame.delay()) {
+                    Duration::ZERO => Self::DEFAULT_DELAY,
+                    delay => delay,
+                };
+                (DynamicImage::ImageRgba8(frame.into_buffer()), delay)
+            })
+            .collect())
+    }
+
+    ///
+    /// The largest size that fits in the region while keeping the image's proportions
+    ///
+    fn fit(img: &DynamicImage, region: &Shape) -> Shape {
+        let (w, h) = (img.width().max(1) as usize, img.height().max(1) as usize);
+        if w <= region.x && h <= region.y {
+            return Shape { x: w, y: h };
+        }
+
+        // Very long or thin images still keep one row or column
+        match w * region.y > h * region.x {
+            // Limited by the width
+            true => Shape {
+                x: region.x,
+                y: ((h * region.x + w / 2) / w).max(1),
+            },
+            false => Shape {
+                x: ((w * region.y + h / 2) / h).max(1),
+                y: region.y,
+            },
+        }
+    }
+
+    ///
+    /// Scale a frame and turn it into LED brightnesses. Transparent pixels are off.
+    ///
+    fn convert(img: &DynamicImage, shape: &Shape, dither: bool) -> Vec<u8> {
+        let img = img.resize_exact(shape.x as u32, shape.y as u32, FilterType::Triangle);
+        let luma: Vec<f32> = img
+            .to_luma_alpha8()
+            .pixels()
+            .map(|p| p.0[0] as f32 * p.0[1] as f32 / 255.0)
+            .collect();
+
+        match dither {
+            true => Self::dither(luma, shape.x),
+            false => luma
+                .iter()
+                .map(|&l| (l * ON_FULL as f32 / 255.0).round() as u8)
+                .collect(),
+        }
+    }
+
+    ///
+    /// Floyd-Steinberg dithering of 0-255 brightnesses down to fully on or off LEDs,
+    /// for drawing without brightness control (draw_bool_matrix)
+    ///
+    fn dither(mut luma: Vec<f32>, width: usize) -> Vec<u8> {
+        let mut out = vec![OFF; luma.len()];
+        for i in 0..luma.len() {
+            let on = luma[i] >= 128.0;
+            out[i] = if on { ON_FULL } else { OFF };
+
+            // Spread the difference to the pixels not drawn yet
+            let error = luma[i] - if on { 255.0 } else { 0.0 };
+            let mut spread = |idx: usize, weight: f32| {
+                if let Some(l) = luma.get_mut(idx) {
+                    *l += error * weight / 16.0;
+                }
+            };
+            let x = i % width;
+            if x + 1 < width {
+                spread(i + 1, 7.0);
+                spread(i + width + 1, 1.0);
+            }
+            if x > 0 {
+                spread(i + width - 1, 3.0);
+            }
+            spread(i + width, 5.0);
+        }
+        out
+    }
+
+    ///
+    /// Move on to the frame that should be showing once `elapsed` more time has passed
+    ///
+    fn step(&mut self, elapsed: Duration) {
+        if self.frames.len() > 1 {
+            // Whole loops of the animation don't change which frame is showing
+            let total: Duration = self.delays.iter().sum();
+            let elapsed = self.elapsed + elapsed;
+            self.elapsed = Duration::from_nanos((elapsed.as_nanos() % total.as_nanos()) as u64);
+
+            while self.elapsed >= self.delays[self.frame] {
+                self.elapsed -= self.delays[self.frame];
+                self.frame = (self.frame + 1) % self.frames.len();
+            }
+        }
+
+        if let Some(frame) = self.frames.get(self.frame) {
+            self.matrix = frame.clone();
+        }
+    }
+}
+
+impl UpdatableWidget for ImageWidget {
+    fn update(&mut self) {
+        let now = Instant::now();
+        self.step(now - self.last_update);
+        self.last_update = now;
+    }
+
+    fn preferred_size(&self) -> Shape {
+        self.shape
+    }
+
+    fn render(&self, view: &mut View) {
+        view.draw(&self.matrix, self.shape.x);
+    }
+
+    fn monochrome(&self) -> bool {
+        self.dither
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockBatteries;
+
+    /// Draw a widget at its preferred size
+    fn draw(widget: &dyn UpdatableWidget) -> Vec<u8> {
+        let size = widget.preferred_size();
+        let mut leds = vec![OFF; size.x * size.y];
+        widget.render(&mut View::new(&mut leds, size.x));
+        leds
+    }
+
+    /// Render a CPU widget for a number of cores with every core at the same usage
+    fn render_cpus(cpus: usize, usage: f32, merge_threads: bool) -> (Shape, Vec<u8>) {
+        let shape = AllCPUsWidget::shape(cpus, merge_threads, None);
+        let usages = vec![usage; cpus];
+        let matrix = AllCPUsWidget::render(&usages, &shape, merge_threads, CpuAggregate::Mean);
+        (shape, matrix)
+    }
+
+    #[test]
+    fn cpu_widget_fits_any_core_count() {
+        for cpus in [4, 6, 16, 24, 32] {
+            for merge_threads in [false, true] {
+                let (shape, matrix) = render_cpus(cpus, 100.0, merge_threads);
+                assert!(shape.x <= 9 && shape.y <= 34, "{} cpus", cpus);
+                assert_eq!(matrix.len(), shape.x * shape.y);
+                assert!(matrix.iter().all(|&led| led == ON_FULL), "{} cpus", cpus);
+            }
+        }
+    }
+
+    #[test]
+    fn cpu_widget_rows() {
+        let rows = |cpus| AllCPUsWidget::shape(cpus, false, None).y;
+        assert_eq!(rows(4), 4);
+        assert_eq!(rows(6), 6);
+        assert_eq!(rows(16), 16);
+        assert_eq!(rows(24), 16);
+        assert_eq!(rows(32), 16);
+
+        assert_eq!(AllCPUsWidget::shape(32, false, Some(32)).y, 32);
+        assert_eq!(AllCPUsWidget::shape(32, false, Some(100)).y, 32);
+        assert_eq!(AllCPUsWidget::shape(4, false, Some(8)).y, 4);
+        assert_eq!(AllCPUsWidget::shape(6, true, None).x, 3);
+        assert_eq!(AllCPUsWidget::shape(32, true, None).x, 9);
+    }
+
+    #[test]
+    fn cpu_widget_idle_shows_first_led() {
+        for cpus in [4, 6, 16, 24, 32] {
+            let (shape, matrix) = render_cpus(cpus, 0.0, false);
+            for (y, row) in matrix.chunks(shape.x).enumerate() {
+                assert_eq!(row[0], ON_FULL, "{} cpus, row {}", cpus, y);
+                assert!(row[1..].iter().all(|&led| led == OFF));
+            }
+        }
+    }
+
+    #[test]
+    fn cpu_widget_groups_cores() {
+        // 24 cores in 16 rows, the 4th row holds cores 4 and 5
+        let mut usages = vec![0.0; 24];
+        usages[5] = 100.0;
+        let fourth_row = |aggregate| {
+            let grouped = AllCPUsWidget::group(&usages, 16, aggregate);
+            assert_eq!(grouped.len(), 16);
+            grouped[3]
+        };
+        assert_eq!(fourth_row(CpuAggregate::Mean), 50.0);
+        assert_eq!(fourth_row(CpuAggregate::Max), 100.0);
+
+        // Every core ends up in exactly one group
+        let grouped = AllCPUsWidget::group(&[10.0; 6], 4, CpuAggregate::Mean);
+        assert_eq!(grouped, vec![10.0; 4]);
+    }
+
+    #[test]
+    fn cpu_widget_merged_odd_core_count() {
+        // 5 cores in 3 columns, the last column holds cores 3 and 4
+        let usages = [0.0, 0.0, 0.0, 0.0, 100.0];
+        let shape = AllCPUsWidget::shape(usages.len(), true, None);
+        let matrix = AllCPUsWidget::render(&usages, &shape, true, CpuAggregate::Max);
+        assert_eq!(shape.x, 3);
+        for (y, row) in matrix.chunks(shape.x).enumerate() {
+            let idle = if y == shape.y - 1 { ON_FULL } else { OFF };
+            assert_eq!(row, [idle, idle, ON_FULL]);
+        }
+    }
+
+    fn temperature_widget(fan: bool) -> TemperatureWidget {
+        TemperatureWidget {
+            components: sysinfo::Components::new(),
+            sensor: None,
+            warn: 70.0,
+            crit: 90.0,
+            hwmon: fan.then(PathBuf::new),
+            max_rpm: 0,
+            blink: false,
+            matrix: vec![],
+            shape: Shape {
+                x: if fan { 7 } else { 3 },
+                y: 12,
+            },
+        }
+    }
+
+    #[test]
+    fn temperature_sensor_by_label() {
+        let labels = ["nvme Composite", "k10temp Tctl", "amdgpu edge"];
+        let find = |wanted| TemperatureWidget::find_sensor(&labels, wanted);
+
+        assert_eq!(find(None), Some(1));
+        assert_eq!(find(Some("EDGE")), Some(2));
+        assert_eq!(find(Some("composite")), Some(0));
+        assert_eq!(find(Some("wifi")), None);
+        assert_eq!(TemperatureWidget::find_sensor(&["acpitz"], None), Some(0));
+        assert_eq!(TemperatureWidget::find_sensor(&[], None), None);
+    }
+
+    #[test]
+    fn temperature_thresholds() {
+        let mut widget = temperature_widget(false);
+        let tube = |matrix: &[u8], y: usize| matrix[y * 3 + 1];
+
+        // 50C is a third of the way from 30C to 90C, bulb and tube lit dim
+        let cool = widget.render(Some(50.0), None);
+        assert_eq!(tube(&cool, 9), ON_DIM);
+        assert_eq!(tube(&cool, 7), ON_DIM);
+        assert_eq!(tube(&cool, 6), OFF);
+        assert_eq!(cool[11 * 3], ON_DIM);
+
+        let warm = widget.render(Some(75.0), None);
+        assert_eq!(tube(&warm, 9), ON_FULL);
+
+        // Critical blinks
+        let hot = widget.render(Some(95.0), None);
+        assert_eq!(tube(&hot, 0), ON_FULL);
+        widget.blink = true;
+        let hot = widget.render(Some(95.0), None);
+        assert!(hot.iter().all(|&led| led == OFF));
+    }
+
+    #[test]
+    fn temperature_without_sensor() {
+        let matrix = temperature_widget(false).render(None, None);
+        assert_eq!(&matrix[30..], [ON_DIM, ON_DIM, ON_DIM, ON_DIM, OFF, ON_DIM]);
+        assert!(matrix[..30].iter().all(|&led| led == OFF));
+    }
+
+    #[test]
+    fn fan_speed_from_hwmon() {
+        let root = std::env::temp_dir().join(format!("ledmatrix-hwmon-{}", std::process::id()));
+        let write = |file: &str, contents: &str| {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        };
+        write("hwmon0/name", "k10temp\n");
+        write("hwmon0/temp1_input", "45000\n");
+        write("hwmon3/name", "cros_ec\n");
+        write("hwmon3/fan1_input", "2400\n");
+        write("hwmon3/fan2_input", "3100\n");
+        write("hwmon3/fan2_label", "Fan 2\n");
+        write("hwmon4/fan1_input", "garbage\n");
+
+        assert_eq!(TemperatureWidget::read_fan_rpm(&root), Some(3100));
+        assert_eq!(TemperatureWidget::read_fan_rpm(&root.join("hwmon0")), None);
+        assert_eq!(TemperatureWidget::read_fan_rpm(&root.join("missing")), None);
+
+        // Fan bar is drawn next to the thermometer
+        let matrix = temperature_widget(true).render(Some(50.0), Some(0.5));
+        let fan_row = |y: usize| &matrix[y * 7 + 3..(y + 1) * 7];
+        assert_eq!(fan_row(11), [OFF, ON_DIM, ON_DIM, ON_DIM]);
+        assert_eq!(fan_row(6), [OFF, ON_DIM, ON_DIM, ON_DIM]);
+        assert_eq!(fan_row(5), [OFF, OFF, OFF, OFF]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    fn battery_widget(
+        reads: Vec<Option<Vec<BatteryReading>>>,
+        index: Option<usize>,
+    ) -> BatteryWidget {
+        let source = Box::new(MockBatteries::new(reads));
+        BatteryWidget::new(source, index, BatteryMode::Bar)
+    }
+
+    /// Number of lit LEDs in the battery's bar
+    fn battery_bar(widget: &BatteryWidget) -> usize {
+        let bar = &draw(widget)[10..16];
+        bar.iter().filter(|&&led| led != OFF).count()
+    }
+
+    #[test]
+    fn battery_missing() {
+        let mut widget = battery_widget(vec![Some(vec![])], None);
+        widget.update();
+
+        let matrix = draw(&widget);
+        assert_eq!(matrix.len(), 9 * 4);
+        assert_eq!(matrix[0], ON_DIM);
+        assert_eq!(
+            &matrix[9..18],
+            [ON_DIM, OFF, OFF, OFF, ON_FULL, ON_FULL, ON_FULL, ON_DIM, ON_DIM]
+        );
+    }
+
+    #[test]
+    fn battery_read_error_keeps_last_reading() {
+        let discharging = MockBatteries::reading(50.0, battery::State::Discharging);
+        let mut widget = battery_widget(vec![Some(vec![discharging]), None], None);
+
+        widget.update();
+        assert_eq!(battery_bar(&widget), 3);
+
+        widget.update();
+        assert_eq!(battery_bar(&widget), 3);
+
+        // Never read successfully
+        let mut widget = battery_widget(vec![None], None);
+        widget.update();
+        assert_eq!(draw(&widget)[9 + 4], ON_FULL);
+    }
+
+    #[test]
+    fn battery_select_or_combine() {
+        let batteries = vec![
+            MockBatteries::reading(100.0, battery::State::Full),
+            MockBatteries::reading(0.0, battery::State::Discharging),
+        ];
+
+        let mut widget = battery_widget(vec![Some(batteries.clone())], None);
+        widget.update();
+        assert_eq!(battery_bar(&widget), 3);
+
+        let mut widget = battery_widget(vec![Some(batteries.clone())], Some(0));
+        widget.update();
+        assert_eq!(battery_bar(&widget), 6);
+
+        // A battery that isn't there is shown as missing
+        let mut widget = battery_widget(vec![Some(batteries)], Some(2));
+        widget.update();
+        assert_eq!(draw(&widget)[9 + 4], ON_FULL);
+    }
+
+    fn battery_mode(reading: BatteryReading, mode: BatteryMode) -> Vec<u8> {
+        let source = Box::new(MockBatteries::new(vec![Some(vec![reading])]));
+        let mut widget = BatteryWidget::new(source, None, mode);
+        widget.update();
+        let shape = widget.preferred_size();
+        assert_eq!(widget.matrix.len(), shape.x * shape.y);
+        draw(&widget)
+    }
+
+    #[test]
+    fn battery_percent_and_power() {
+        let reading = MockBatteries::reading(42.0, battery::State::Discharging);
+        assert_eq!(
+            battery_mode(reading, BatteryMode::Percent),
+            ClockWidget::render_number(42)
+        );
+        // Too many digits for 100%, shown as FL instead
+        let full = MockBatteries::reading(100.0, battery::State::Full);
+        let mut expected = Bitmap::new(9, 5);
+        expected.blit(&Font::small().render("FL"), 1, 0);
+        assert_eq!(battery_mode(full, BatteryMode::Percent), expected.pixels);
+
+        // 10W draw, dimmed while charging
+        assert_eq!(
+            battery_mode(reading, BatteryMode::Power),
+            ClockWidget::render_number(10)
+        );
+        let charging = MockBatteries::reading(42.0, battery::State::Charging);
+        let matrix = battery_mode(charging, BatteryMode::Power);
+        assert!(matrix.iter().all(|&led| led == OFF || led == ON_DIM));
+    }
+
+    #[test]
+    fn battery_time_remaining() {
+        let mut reading = MockBatteries::reading(42.0, battery::State::Discharging);
+        reading.time_to_empty = Some(3900.0);
+
+        let mut expected = ClockWidget::render_number(1);
+        expected.extend(vec![OFF; 9]);
+        expected.extend(ClockWidget::render_number(5));
+        assert_eq!(battery_mode(reading, BatteryMode::Time), expected);
+
+        // No estimate yet
+        let unknown = MockBatteries::reading(42.0, battery::State::Unknown);
+        let matrix = battery_mode(unknown, BatteryMode::Time);
+        assert_eq!(matrix.iter().filter(|&&led| led == ON_DIM).count(), 12);
+    }
+
+    fn memory_widget(style: MemoryStyle, ram: f32, swap: f32) -> Vec<u8> {
+        let mut widget = MemoryWidget::new(style, true, None, None);
+        widget.render_usage(ram, swap);
+        let shape = widget.preferred_size();
+        assert_eq!(widget.matrix.len(), shape.x * shape.y);
+        draw(&widget)
+    }
+
+    #[test]
+    fn memory_ram_and_swap() {
+        // RAM in rows 0-1 (bright), a gap, then swap in rows 3-4 (dim)
+        let matrix = memory_widget(MemoryStyle::Horizontal, 1.0, 0.33);
+        let row = |y: usize| &matrix[y * 9..(y + 1) * 9];
+        assert_eq!(row(0), [ON_FULL; 9]);
+        assert_eq!(row(1), [ON_FULL; 9]);
+        assert_eq!(row(2), [OFF; 9]);
+        for y in 3..5 {
+            assert_eq!(row(y)[..3], [ON_DIM; 3]);
+            assert_eq!(row(y)[3..], [OFF; 6]);
+        }
+
+        // Side by side, filling from the bottom up
+        let matrix = memory_widget(MemoryStyle::Vertical, 0.5, 0.0);
+        for y in 0..10 {
+            let lit = if y >= 5 { ON_FULL } else { OFF };
+            assert_eq!(matrix[y * 9..y * 9 + 4], [lit; 4], "row {}", y);
+            assert_eq!(matrix[y * 9 + 4..(y + 1) * 9], [OFF; 5], "row {}", y);
+        }
+    }
+
+    #[test]
+    fn memory_percent() {
+        let mut expected = ClockWidget::render_number(42);
+        expected.extend(vec![OFF; 9]);
+        // Swap is dimmed
+        let swap = ClockWidget::render_number(7);
+        expected.extend(swap.iter().map(|&led| led.min(ON_DIM)));
+        assert_eq!(memory_widget(MemoryStyle::Percent, 0.42, 0.07), expected);
+
+        let mut widget = MemoryWidget::new(MemoryStyle::Percent, false, None, None);
+        widget.render_usage(0.42, 0.07);
+        assert_eq!(draw(&widget), ClockWidget::render_number(42));
+
+        // Completely full isn't shown as 99
+        widget.render_usage(1.0, 0.0);
+        assert_eq!(draw(&widget), render_percent(100));
+        assert_ne!(draw(&widget), ClockWidget::render_number(99));
+    }
+
+    #[test]
+    fn disk_blinks_when_almost_full() {
+        let mounts = vec!["/a".to_string(), "/b".to_string()];
+        let mut widget = DiskWidget::new(mounts, 90.0, 2);
+        assert_eq!(widget.preferred_size(), Shape { x: 9, y: 5 });
+
+        // Lit LEDs in the top row of each bar
+        let mut lit = |used: &[f32]| {
+            widget.render_usage(used);
+            let matrix = draw(&widget);
+            [0, 3].map(|y| {
+                matrix[y * 9..(y + 1) * 9]
+                    .iter()
+                    .filter(|&&led| led == ON_FULL)
+                    .count()
+            })
+        };
+
+        // The full disk is hidden every other frame, the other one stays
+        assert_eq!(lit(&[0.95, 0.5]), [0, 5]);
+        assert_eq!(lit(&[0.95, 0.5]), [9, 5]);
+        assert_eq!(lit(&[0.95, 0.5]), [0, 5]);
+
+        // Below the threshold nothing blinks
+        assert_eq!(lit(&[0.85, 0.5]), [8, 5]);
+        assert_eq!(lit(&[0.85, 0.5]), [8, 5]);
+    }
+
+    #[test]
+    fn disk_mount_count() {
+        // Nothing given shows the default mount rather than an empty widget
+        let widget = DiskWidget::new(vec![], 90.0, 2);
+        assert_eq!(widget.mounts, [DiskWidget::default_mount()]);
+        assert_eq!(widget.preferred_size(), Shape { x: 9, y: 2 });
+
+        // Mounts that don't fit on the module are dropped
+        let mounts = (0..15).map(|idx| format!("/mnt/{}", idx)).collect();
+        let widget = DiskWidget::new(mounts, 90.0, 4);
+        assert_eq!(widget.mounts.len(), 7);
+        assert_eq!(widget.mounts[6], "/mnt/6");
+        assert_eq!(widget.preferred_size(), Shape { x: 9, y: 34 });
+    }
+
+    /// One column of a matrix, top to bottom
+    fn column(matrix: &[u8], width: usize, x: usize) -> Vec<u8> {
+        matrix.iter().skip(x).step_by(width).copied().collect()
+    }
+
+    #[test]
+    fn network_graph() {
+        let mut widget = NetworkWidget::new(None, Some(3), Some(10));
+        widget.push(2048.0, 1024.0);
+        widget.push(4096.0, 4096.0);
+        let matrix = draw(&widget);
+
+        // Scaled to the busiest sample, download up from the middle and upload down
+        let (f, d) = (ON_FULL, ON_DIM);
+        assert_eq!(column(&matrix, 3, 0), [OFF; 10]);
+        assert_eq!(column(&matrix, 3, 1), [0, 0, f, f, f, d, 0, 0, 0, 0]);
+        assert_eq!(column(&matrix, 3, 2), [f, f, f, f, f, d, d, d, d, d]);
+
+        // Once the busy sample scrolls off, the graph scales back down
+        widget.push(1024.0, 0.0);
+        widget.push(1024.0, 0.0);
+        assert_eq!(column(&draw(&widget), 3, 2), [0, 0, 0, 0, f, 0, 0, 0, 0, 0]);
+        widget.push(1024.0, 0.0);
+        assert_eq!(column(&draw(&widget), 3, 2), [f, f, f, f, f, 0, 0, 0, 0, 0]);
+
+        // But not below the minimum, so idle traffic stays small
+        for _ in 0..3 {
+            widget.push(512.0, 0.0);
+        }
+        let matrix = draw(&widget);
+        assert_eq!(column(&matrix, 3, 2), [0, 0, f, f, f, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn cpu_history_scrolls() {
+        let mut widget = CpuHistoryWidget::new(false, Some(3), Some(4));
+        let f = ON_FULL;
+
+        widget.push(100.0);
+        let matrix = draw(&widget);
+        assert_eq!(column(&matrix, 3, 1), [OFF; 4]);
+        assert_eq!(column(&matrix, 3, 2), [f; 4]);
+
+        // Each sample moves one column to the left
+        widget.push(50.0);
+        let matrix = draw(&widget);
+        assert_eq!(column(&matrix, 3, 0), [OFF; 4]);
+        assert_eq!(column(&matrix, 3, 1), [f; 4]);
+        assert_eq!(column(&matrix, 3, 2), [0, 0, f, f]);
+    }
+
+    #[test]
+    fn cpu_history_percent() {
+        let mut widget = CpuHistoryWidget::new(true, None, Some(4));
+        assert_eq!(widget.preferred_size(), Shape { x: 9, y: 10 });
+
+        widget.push(42.0);
+        let matrix = draw(&widget);
+
+        // The newest sample as digits, a gap, then the graph
+        assert_eq!(matrix[..45], ClockWidget::render_number(42));
+        assert_eq!(matrix[45..54], [OFF; 9]);
+        assert_eq!(column(&matrix, 9, 8)[6..], [0, 0, ON_FULL, ON_FULL]);
+        assert_eq!(column(&matrix, 9, 7)[6..], [OFF; 4]);
+    }
+
+    fn clock_at(clock: &ClockWidget, time: &str) -> Vec<u8> {
+        let time = chrono::NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S").unwrap();
+        let matrix = clock.render(time);
+        assert_eq!(matrix.len(), clock.shape.x * clock.shape.y);
+        matrix
+    }
+
+    #[test]
+    fn clock_twelve_hour() {
+        let clock = ClockWidget::new(true, ClockSeconds::None, ClockMode::Time, None);
+
+        // 3:07 PM, without the leading zero and with the PM dot
+        let pm = clock_at(&clock, "2024-05-01 15:07:00");
+        let mut expected = ClockWidget::render_number(3);
+        for row in expected.chunks_mut(9) {
+            row[1..4].fill(OFF);
+        }
+        expected[9 * 4 + 8] = ON_FULL;
+        expected.extend([OFF; 9]);
+        expected.extend(ClockWidget::render_number(7));
+        assert_eq!(pm, expected);
+
+        // 12:30 AM
+        let am = clock_at(&clock, "2024-05-01 00:30:00");
+        assert_eq!(am[..45], ClockWidget::render_number(12));
+    }
+
+    #[test]
+    fn clock_seconds_and_date() {
+        let bar = ClockWidget::new(false, ClockSeconds::Bar, ClockMode::Time, None);
+        let matrix = clock_at(&bar, "2024-05-01 15:07:59");
+        assert_eq!(matrix[..45], ClockWidget::render_number(15));
+        assert_eq!(matrix[9 * 12..], [ON_DIM; 9]);
+
+        let digits = ClockWidget::new(false, ClockSeconds::Digits, ClockMode::Time, None);
+        let matrix = clock_at(&digits, "2024-05-01 15:07:42");
+        let seconds: Vec<u8> = ClockWidget::render_number(42)
+            .into_iter()
+            .map(|led| led.min(ON_DIM))
+            .collect();
+        assert_eq!(matrix[9 * 12..], seconds);
+
+        // Seconds aren't shown with the date
+        let date = ClockWidget::new(false, ClockSeconds::Digits, ClockMode::Date, None);
+        let matrix = clock_at(&date, "2024-05-01 15:07:42");
+        assert_eq!(matrix[..45], ClockWidget::render_number(1));
+        assert_eq!(matrix[54..], ClockWidget::render_number(5));
+    }
+
+    #[test]
+    fn clock_timezone() {
+        let clock = |tz| ClockWidget::new(false, ClockSeconds::None, ClockMode::Time, Some(tz));
+
+        let kolkata = clock("Asia/Kolkata");
+        assert_eq!(kolkata.timezone, Some(chrono_tz::Asia::Kolkata));
+        let offset = kolkata.now() - chrono::Utc::now().naive_utc();
+        let error = offset - chrono::Duration::minutes(330);
+        assert!(error.num_seconds().abs() < 5);
+
+        assert_eq!(clock("Mars/Olympus").timezone, None);
+    }
+
+    #[test]
+    fn text_fits() {
+        let mut widget = TextWidget::new("42", Font::small(), false, None, 1);
+        assert_eq!((widget.shape.x, widget.shape.y), (9, 5));
+
+        // Short text stays put
+        let mut expected = Bitmap::new(9, 5);
+        expected.blit(&Font::small().render("42"), 0, 0);
+        for _ in 0..3 {
+            widget.update();
+            assert_eq!(draw(&widget), expected.pixels);
+        }
+    }
+
+    #[test]
+    fn text_marquee() {
+        // 4 glyphs make 15 LEDs of text, scrolling around every 18 steps
+        let mut widget = TextWidget::new("ABCD", Font::small(), false, None, 2);
+        let text = Font::small().render("ABCD");
+
+        let frames: Vec<Vec<u8>> = (0..10)
+            .map(|_| {
+                widget.update();
+                draw(&widget)
+            })
+            .collect();
+
+        let mut expected = Bitmap::new(9, 5);
+        expected.blit(&text, -4, 0);
+        assert_eq!(frames[2], expected.pixels);
+
+        // The start of the text comes back in after the gap
+        let mut expected = Bitmap::new(9, 5);
+        expected.blit(&text, -14, 0);
+        expected.blit(&text, 4, 0);
+        assert_eq!(frames[7], expected.pixels);
+        assert_eq!(frames[9], widget.render(0));
+    }
+
+    #[test]
+    fn text_rotated() {
+        let mut widget = TextWidget::new("HELLO WORLD", Font::small(), true, None, 1);
+        assert_eq!((widget.shape.x, widget.shape.y), (5, 34));
+
+        widget.update();
+        let text = Font::small().rotated().render("HELLO WORLD");
+        assert_eq!(draw(&widget)[..5 * 34], text.pixels[..5 * 34]);
+    }
+
+    /// An image file in its own temporary directory, which is removed when dropped
+    struct ImageFile(PathBuf);
+
+    impl ImageFile {
+        fn new(name: &str, contents: &[u8]) -> ImageFile {
+            let dir = std::env::temp_dir().join(format!(
+                "ledmatrix-image-{}-{}",
+                std::process::id(),
+                name
+            ));
+            fs::create_dir_all(&dir).unwrap();
+            let path = dir.join(name);
+            fs::write(&path, contents).unwrap();
+            ImageFile(path)
+        }
+    }
+
+    impl Drop for ImageFile {
+        fn drop(&mut self) {
+            if let Some(dir) = self.0.parent() {
+                let _ = fs::remove_dir_all(dir);
+            }
+        }
+    }
+
+    #[test]
+    fn image_greyscale() {
+        let mut pgm = b"P5 4 2 255\n".to_vec();
+        pgm.extend([0, 255, 51, 255, 255, 0, 0, 0]);
+        let file = ImageFile::new("grey.pgm", &pgm);
+        let mut widget = ImageWidget::new(&file.0, None, None, false);
+        widget.update();
+        assert!(!widget.monochrome());
+
+        assert_eq!((widget.shape.x, widget.shape.y), (4, 2));
+        assert_eq!(
+            draw(&widget),
+            vec![OFF, ON_FULL, 24, ON_FULL, ON_FULL, OFF, OFF, OFF]
+        );
+
+        // Black is 1 in a PBM
+        let file = ImageFile::new("mono.pbm", b"P1\n3 1\n1 0 1\n");
+        let mut widget = ImageWidget::new(&file.0, None, None, false);
+        widget.update();
+        assert_eq!(draw(&widget), vec![OFF, ON_FULL, OFF]);
+    }
+
+    #[test]
+    fn image_missing() {
+        let mut widget = ImageWidget::new(Path::new("/nonexistent.png"), None, None, true);
+        widget.update();
+        assert_eq!((widget.shape.x, widget.shape.y), (0, 0));
+        assert!(draw(&widget).is_empty());
+    }
+
+    #[test]
+    fn image_fits_region() {
+        let region = Shape { x: 9, y: 34 };
+        let fit = |w, h| ImageWidget::fit(&DynamicImage::new_luma8(w, h), &region);
+
+        let wide = fit(90, 17);
+        assert_eq!((wide.x, wide.y), (9, 2));
+        let tall = fit(20, 100);
+        assert_eq!((tall.x, tall.y), (7, 34));
+        // Small images aren't scaled up
+        let small = fit(5, 5);
+        assert_eq!((small.x, small.y), (5, 5));
+        // Extreme aspect ratios don't round down to nothing
+        let line = fit(1000, 1);
+        assert_eq!((line.x, line.y), (9, 1));
+        let column = fit(1, 1000);
+        assert_eq!((column.x, column.y), (1, 34));
+    }
+
+    #[test]
+    fn image_dithering() {
+        let dithered = ImageWidget::dither(vec![128.0; 64], 8);
+        assert!(dithered.iter().all(|&led| led == ON_FULL || led == OFF));
+        assert_eq!(dithered.iter().filter(|&&led| led == ON_FULL).count(), 32);
+        // Half grey comes out as a checkerboard
+        assert_eq!(&dithered[..4], [ON_FULL, OFF, ON_FULL, OFF]);
+        assert_eq!(&dithered[8..12], [OFF, ON_FULL, OFF, ON_FULL]);
+
+        assert_eq!(ImageWidget::dither(vec![255.0; 9], 3), vec![ON_FULL; 9]);
+        assert_eq!(ImageWidget::dither(vec![0.0; 9], 3), vec![OFF; 9]);
+
+        // Dithered images are drawn without brightness control
+        let mut pgm = b"P5 4 1 255\n".to_vec();
+        pgm.extend([0, 100, 200, 255]);
+        let file = ImageFile::new("dither.pgm", &pgm);
+        let mut widget = ImageWidget::new(&file.0, None, None, true);
+        widget.update();
+        assert!(widget.monochrome());
+        let matrix = draw(&widget);
+        assert!(matrix.iter().all(|&led| led == ON_FULL || led == OFF));
+    }
+
+    #[test]
+    fn gif_frames() {
+        let frame = |luma: u8, ms: u32| {
+            let buffer = image::RgbaImage::from_pixel(2, 2, image::Rgba([luma, luma, luma, 255]));
+            image::Frame::from_parts(buffer, 0, 0, image::Delay::from_numer_denom_ms(ms, 1))
+        };
+        let mut gif = vec![];
+        image::codecs::gif::GifEncoder::new(&mut gif)
+            .encode_frames([frame(0, 100), frame(255, 200), frame(128, 0)])
+            .unwrap();
+
+        let file = ImageFile::new("anim.gif", &gif);
+        let mut widget = ImageWidget::new(&file.0, None, None, true);
+        let mut after = |ms: u64| {
+            widget.step(Duration::from_millis(ms));
+            draw(&widget)
+        };
+        let black = vec![OFF; 4];
+        let white = vec![ON_FULL; 4];
+        let grey = vec![ON_FULL, OFF, OFF, ON_FULL];
+
+        assert_eq!(after(0), black);
+        assert_eq!(after(60), black);
+        // Shown for as long as each frame asks for
+        assert_eq!(after(60), white);
+        assert_eq!(after(170), white);
+        assert_eq!(after(10), grey);
+        // Frames without a delay are shown for 100ms
+        assert_eq!(after(100), black);
+
+        // Frames that are too short to be shown are skipped
+        assert_eq!(after(350), grey);
+        // A whole loop later, the same frame is showing
+        assert_eq!(after(4000), grey);
     }
 }